@@ -0,0 +1,67 @@
+use std::{error::Error, hint::black_box, time::{Duration, Instant}};
+
+use homeboy::templating::{matcher::{CompiledTemplateSet, TemplateMatcher}, parser::TemplateParser, template::Template};
+
+const NUM_TEMPLATES: usize = 300;
+const ITERATIONS: usize = 20;
+
+// Compares matching by rebuilding each template's regex per utterance against matching with a precompiled set
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut matcher = TemplateMatcher::new();
+    matcher.add_subtemplate("ask", TemplateParser::parse_template("((can|would|could) you)? please?")?);
+
+    let templates = (0..NUM_TEMPLATES)
+        .map(|i| TemplateParser::parse_template(&format!("{{ask}}? command {} [thing] (on {})?", to_words(i), to_words(i))))
+        .collect::<Result<Vec<Template>, _>>()?;
+    let inputs = vec![
+        format!("could you please command {} the lights on {}", to_words(NUM_TEMPLATES - 1), to_words(NUM_TEMPLATES - 1)),
+        format!("command {} something", to_words(NUM_TEMPLATES / 2)),
+        String::from("this utterance does not match anything"),
+    ];
+
+    let uncompiled = time(|| {
+        for input in &inputs {
+            let found = templates
+                .iter()
+                .find_map(|t| matcher.try_match(input, t).unwrap());
+            black_box(found);
+        }
+    });
+
+    let set = CompiledTemplateSet::new(
+        templates.iter().map(|t| matcher.compile(t)).collect::<Result<Vec<_>, _>>()?
+    )?;
+    let compiled = time(|| {
+        for input in &inputs {
+            let found = set
+                .candidates(input)
+                .into_iter()
+                .find_map(|i| set.get(i).unwrap().try_match(input));
+            black_box(found);
+        }
+    });
+
+    println!("{} templates, {} utterances, {} iterations", NUM_TEMPLATES, inputs.len(), ITERATIONS);
+    println!("  regex per try_match: {:?} per iteration", uncompiled);
+    println!("  precompiled set:     {:?} per iteration", compiled);
+    println!("  speedup:             {:.1}x", uncompiled.as_secs_f64() / compiled.as_secs_f64());
+    Ok(())
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS as u32
+}
+
+// Spells numbers out digit by digit, so the templates differ by words the way spoken commands do
+fn to_words(n: usize) -> String {
+    const DIGITS: [&str; 10] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+    n.to_string()
+        .chars()
+        .map(|c| DIGITS[c.to_digit(10).unwrap() as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
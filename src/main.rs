@@ -1,6 +1,6 @@
 use dotenv::dotenv;
-use homeboy::runner::{runner::CommandRunner, voice::deepgram::OutputMode};
-use std::{env, error::Error, io::{stdin, stdout, Write}};
+use homeboy::{runner::{runner::CommandRunner, voice::deepgram::OutputMode}, templating::{fuzzy::FuzzyOptions, normalizer::Normalizer}};
+use std::{env, error::Error, io::{stdin, stdout, Write}, process};
 
 #[allow(dead_code)]
 const INPUT_VOICE: i32 = 0;
@@ -11,17 +11,52 @@ const INPUT: i32 = INPUT_CONSOLE;
 
 const OUTPUT_MODE: OutputMode = OutputMode::Console;
 
+const TEMPLATE_PATH: &str = "./templates.txt";
+// Templates with this tag use Spotify, and are turned off if it can't be connected to
+const SPOTIFY_TAG: &str = "music";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
     let _vars = env::vars();
 
     let mut runner = CommandRunner::new()?;
+    // Match "what's" the same as "what is", and "café" the same as "cafe"
+    runner.set_normalizer(Normalizer::standard())?;
+    // Run "pause the music and then tell me the weather" as two commands
+    runner.set_compound_splitting(true);
+
+    // `homeboy check [path]` checks the examples in the template file(s) instead of running
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|a| a == "check") {
+        let path = args.get(2).map_or(TEMPLATE_PATH, |p| p.as_str());
+        let failures = runner.check_templates(path)?;
+        for failure in &failures {
+            println!("{}", failure);
+        }
+        if !failures.is_empty() {
+            println!("{} example(s) failed", failures.len());
+            process::exit(1);
+        }
+        println!("All examples passed");
+        return Ok(());
+    }
+    // `homeboy graph [path]` prints the templates as a Graphviz DOT graph, e.g. for `dot -Tsvg`
+    if args.get(1).is_some_and(|a| a == "graph") {
+        let path = args.get(2).map_or(TEMPLATE_PATH, |p| p.as_str());
+        print!("{}", runner.template_graph(path)?);
+        return Ok(());
+    }
+
     println!("Initializing...");
-    runner.init("./templates.txt", OUTPUT_MODE)?;
+    runner.set_spotify_tag(Some(SPOTIFY_TAG));
+    runner.init(TEMPLATE_PATH, OUTPUT_MODE)?;
     println!("Initialized");
 
     if INPUT == INPUT_VOICE {
+        // Transcripts often contain near-miss words, so match them leniently
+        runner.set_fuzzy_matching(Some(FuzzyOptions::default()));
+
         let devices = runner.get_input_devices()?;
         println!("Select Input Device:");
         for (i, dev) in devices.iter().enumerate() {
@@ -36,7 +71,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
         loop {
             print!("Input: ");
             let line = read_line();
-            runner.run(&line)?;
+            if let Some(utterance) = line.strip_prefix(":explain ") {
+                runner.explain(utterance)?;
+            } else {
+                runner.run(&line)?;
+            }
         }
     }
 
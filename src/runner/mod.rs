@@ -4,3 +4,4 @@ pub mod voice;
 pub mod location;
 pub mod memory;
 pub mod search;
+pub mod watch;
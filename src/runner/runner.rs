@@ -1,14 +1,17 @@
 use std::{cell::RefCell, env, error::Error, path::Path, rc::Rc};
-use cortex_lang::{interpreting::{interpreter::CortexInterpreter, value::CortexValue}, parsing::ast::{expression::{OptionalIdentifier, Parameter, PathIdent}, top_level::{Body, PFunction, Struct}, r#type::{CortexType, TypeParam}}, preprocessing::module::Module};
+use cortex_lang::{interpreting::{interpreter::CortexInterpreter, value::CortexValue}, parsing::ast::{expression::{OptionalIdentifier, PExpression, Parameter, PathIdent}, top_level::{Body, PFunction, Struct}, r#type::{CortexType, TypeParam}}, preprocessing::module::Module};
 use futures::executor::block_on;
 
 use openweathermap::Volume;
 use rdev::{listen, Event, EventType, Key, ListenError};
 use thiserror::Error;
 
-use crate::templating::handler::TemplateHandler;
+use crate::templating::{fuzzy::FuzzyOptions, handler::{EntityResolver, TemplateHandler}, normalizer::{self, Normalizer}, slots::{self, SlotValue}, validation::ExampleFailure};
 
-use super::{location, memory::memory::{Memory, MemoryValue}, search::search::WebSummarizer, spotify::spotify::Spotify, voice::{deepgram::{DeepgramClient, OutputMode}, record::Recorder}};
+use super::{location, memory::memory::{Memory, MemoryValue}, search::search::WebSummarizer, spotify::spotify::Spotify, voice::{deepgram::{DeepgramClient, OutputMode}, record::Recorder}, watch::watch::FileWatcher};
+
+// How many templates are suggested when no template matches the input
+const SUGGESTION_LIMIT: usize = 3;
 
 macro_rules! unwrap_enum {
     ($e:expr, $p:pat => $v:expr) => {
@@ -23,15 +26,33 @@ macro_rules! unwrap_enum {
 pub enum RunnerError {
     #[error("Binding for required parameter '{0}' not found")]
     BindingNotFound(String),
-    #[error("Invalid parameter type '{0}'. Parameters must be string or string?")]
+    #[error("Invalid parameter type '{0}'. Parameters must be string, number, bool or &list<string> (or optional versions of these)")]
     InvalidParameterType(String),
+    #[error("Binding '{1}' cannot be passed to parameter '{0}'")]
+    InvalidBinding(String, String),
     #[error("There was a listen error")]
     ListenError(ListenError),
+    #[error("{0} is not available, since the templates were loaded without setting up services")]
+    ServiceUnavailable(&'static str),
+}
+
+/// An input that matched several templates equally well, waiting for the user to say which one they meant
+pub struct PendingChoice {
+    pub input: String,
+    /// The matched templates as described to the user
+    pub options: Vec<String>,
+    /// Compound command parts that run once the question is answered
+    pub remaining: Vec<(usize, String)>,
 }
 
 pub struct CommandRunner {
     handler: TemplateHandler,
     interpreter: CortexInterpreter,
+    template_path: String,
+    template_watcher: Option<FileWatcher>,
+    split_compound: bool,
+    spotify_tag: Option<String>,
+    pending_choice: Option<PendingChoice>,
 
     spotify: Option<Rc<RefCell<Spotify>>>,
     deepgram: Option<Rc<RefCell<DeepgramClient>>>,
@@ -49,6 +70,11 @@ impl CommandRunner {
             CommandRunner {
                 handler: TemplateHandler::new(),
                 interpreter: CortexInterpreter::new()?,
+                template_path: String::new(),
+                template_watcher: None,
+                split_compound: false,
+                spotify_tag: None,
+                pending_choice: None,
 
                 spotify: None,
                 deepgram: None,
@@ -62,19 +88,133 @@ impl CommandRunner {
         )
     }
 
-    pub fn init(&mut self, template_filepath: &str, output_mode: OutputMode) -> Result<(), Box<dyn Error>> {
+    /// Sets up the runner. `template_path` may be a single template file or a directory of `*.tmpl` files.
+    pub fn init(&mut self, template_path: &str, output_mode: OutputMode) -> Result<(), Box<dyn Error>> {
+        self.init_services(output_mode)?;
+        self.register_modules()?;
+        self.load_templates_internal(template_path)?;
+
+        let spotify_result = block_on(self.spotify.as_mut().unwrap().borrow_mut().init());
+        match (spotify_result, &self.spotify_tag) {
+            (Err(error), Some(tag)) => {
+                println!("Could not connect to Spotify, disabling templates tagged {}: {}", tag, error);
+                self.handler.set_tag_enabled(tag, false);
+            },
+            (result, _) => result?,
+        }
+
+        Ok(())
+    }
+    // Creates the services that template functions use, without connecting to any of them
+    fn init_services(&mut self, output_mode: OutputMode) -> Result<(), Box<dyn Error>> {
         self.spotify = Some(Rc::new(RefCell::new(Spotify::new())));
         self.deepgram = Some(Rc::new(RefCell::new(DeepgramClient::init(output_mode)?)));
         self.memory = Some(Rc::new(RefCell::new(Memory::load(env::var("memory_path")?)?)));
         self.search = Some(Rc::new(RefCell::new(WebSummarizer::new()?)));
 
         self.recorder = Some(Rc::new(RefCell::new(Recorder::new())));
+        Ok(())
+    }
+
+    /// Loads the templates without setting up the services that need credentials or local files (voice, memory and
+    /// search), so that templates can be checked anywhere. Template functions that use those services fail if run,
+    /// and entities declared with `memory=<key>` only have the values written in the template file. Use this
+    /// instead of `init`.
+    pub fn load_templates(&mut self, template_path: &str) -> Result<(), Box<dyn Error>> {
+        self.spotify = Some(Rc::new(RefCell::new(Spotify::new())));
         self.register_modules()?;
-        self.handler.load_from_file(template_filepath, &mut self.interpreter)?;
+        self.load_templates_internal(template_path)
+    }
+    fn load_templates_internal(&mut self, template_path: &str) -> Result<(), Box<dyn Error>> {
+        self.handler.set_entity_resolver(self.entity_resolver());
+        self.handler.load(template_path, &mut self.interpreter)?;
+        self.template_path = String::from(template_path);
+        self.template_watcher = Some(FileWatcher::new(self.handler.sources()));
+        Ok(())
+    }
+
+    /// Loads the templates (as `load_templates` does) and checks their `% example` lines, returning the examples
+    /// that failed. Nothing is run.
+    pub fn check_templates(&mut self, template_path: &str) -> Result<Vec<ExampleFailure>, Box<dyn Error>> {
+        self.load_templates(template_path)?;
+        Ok(self.handler.check())
+    }
+    /// Loads the templates (as `load_templates` does) and returns them as a Graphviz DOT graph. Nothing is run.
+    pub fn template_graph(&mut self, template_path: &str) -> Result<String, Box<dyn Error>> {
+        self.load_templates(template_path)?;
+        Ok(self.handler.to_dot())
+    }
+    pub fn handler(&self) -> &TemplateHandler {
+        &self.handler
+    }
+    /// The question waiting to be answered by the next input, if the last input was ambiguous
+    pub fn pending_choice(&self) -> Option<&PendingChoice> {
+        self.pending_choice.as_ref()
+    }
+    // Entities declared with `memory=<key>` take their values from the list stored under that key
+    fn entity_resolver(&self) -> Option<EntityResolver> {
+        let memory = self.memory.clone()?;
+        Some(Box::new(move |key| match memory.borrow().get(&String::from(key)) {
+            Some(MemoryValue::List(values)) => Some(values),
+            _ => None,
+        }))
+    }
 
-        block_on(self.spotify.as_mut().unwrap().borrow_mut().init())?;
+    /// Turns the templates with the given tag on or off
+    pub fn set_tag_enabled(&mut self, tag: &str, enabled: bool) {
+        self.handler.set_tag_enabled(tag, enabled);
+    }
 
-        Ok(())
+    pub fn set_fuzzy_matching(&mut self, options: Option<FuzzyOptions>) {
+        self.handler.set_fuzzy(options);
+    }
+    /// Runs utterances that join several commands, such as "pause the music and then tell me the weather", as
+    /// separate commands when each one matches a template
+    pub fn set_compound_splitting(&mut self, enabled: bool) {
+        self.split_compound = enabled;
+    }
+    /// Names the tag of the templates that use Spotify. If set, `init` turns those templates off when it can't
+    /// connect to Spotify instead of failing. Templates that use Spotify without the tag will fail when run.
+    pub fn set_spotify_tag(&mut self, tag: Option<&str>) {
+        self.spotify_tag = tag.map(String::from);
+    }
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) -> Result<(), Box<dyn Error>> {
+        self.handler.set_normalizer(normalizer)
+    }
+
+    /// Loads the templates again, replacing the current ones only if the new ones load successfully
+    pub fn reload_templates(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut handler = TemplateHandler::new();
+        handler.set_fuzzy(self.handler.get_fuzzy().cloned());
+        handler.set_normalizer(self.handler.get_normalizer().clone())?;
+        handler.set_entity_resolver(self.entity_resolver());
+        for tag in self.handler.disabled_tags() {
+            handler.set_tag_enabled(tag, false);
+        }
+        let result = handler.load(&self.template_path, &mut self.interpreter);
+        if result.is_ok() {
+            self.handler = handler;
+            self.template_watcher = Some(FileWatcher::new(self.handler.sources()));
+        } else {
+            // The failed attempt may have included files the previous templates didn't, and fixing one of those
+            // needs to trigger another reload as much as fixing a file already in use
+            let mut sources = self.handler.sources().to_vec();
+            sources.extend(handler.sources().iter().filter(|s| !self.handler.sources().contains(s)).cloned());
+            self.template_watcher = Some(FileWatcher::new(&sources));
+        }
+        result
+    }
+    fn reload_templates_if_changed(&mut self) {
+        let changed = self.template_watcher.as_ref().is_some_and(|w| w.has_changed());
+        if changed {
+            match self.reload_templates() {
+                Ok(()) => println!("Templates reloaded"),
+                Err(error) => {
+                    println!("{}", error);
+                    println!("Error when reloading templates, keeping the previous templates");
+                },
+            }
+        }
     }
 
     pub fn get_input_devices(&self) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
@@ -155,34 +295,175 @@ impl CommandRunner {
         Ok(())
     }
     pub fn run(&mut self, input: &str) -> Result<(), Box<dyn Error>> {
-        let sanitized_input: String = input.to_lowercase()
-            .chars()
-            .filter(|c| c.is_alphanumeric() || c.is_whitespace())
-            .collect();
-        let result = self.handler.find_function(sanitized_input.as_str())?;
+        self.reload_templates_if_changed();
+        // Functions may have changed the remembered values of entities since the last command
+        self.handler.refresh_entities()?;
+        if let Some(choice) = self.pending_choice.take() {
+            return self.resolve_choice(choice, input);
+        }
+        if self.split_compound {
+            let (sanitized_input, sanitize_map) = normalizer::sanitize(input);
+            let parts = self.handler.split_compound(&sanitized_input).map(|parts| {
+                parts.into_iter().filter_map(|part| sanitize_map.original_range(part)).collect::<Vec<_>>()
+            });
+            if let Some(parts) = parts {
+                self.run_parts(parts.into_iter().enumerate().map(|(i, part)| (i + 1, String::from(&input[part]))).collect());
+                return Ok(());
+            }
+        }
+        self.run_command(input, None)
+    }
+    // Runs the parts in turn, stopping to wait if one of them asks which template was meant
+    fn run_parts(&mut self, parts: Vec<(usize, String)>) {
+        let mut parts = parts.into_iter();
+        while let Some((number, part)) = parts.next() {
+            println!("Command {}: \"{}\"", number, part);
+            if let Err(error) = self.run_command(&part, None) {
+                println!("{}", error);
+                println!("Error when running command {}", number);
+            }
+            if let Some(choice) = self.pending_choice.as_mut() {
+                choice.remaining = parts.collect();
+                return;
+            }
+        }
+    }
+    // Runs the chosen template and any parts waiting on it, or runs the input as a new command if it picks none
+    fn resolve_choice(&mut self, choice: PendingChoice, input: &str) -> Result<(), Box<dyn Error>> {
+        let (answer, _) = normalizer::sanitize(input);
+        let Some(index) = self.handler.choose(&answer, &choice.options) else {
+            return self.run(input);
+        };
+        let result = self.run_command(&choice.input, Some(index));
+        if choice.remaining.is_empty() {
+            return result;
+        }
+        if let Err(error) = result {
+            println!("{}", error);
+            println!("Error when running the chosen command");
+        }
+        self.run_parts(choice.remaining);
+        Ok(())
+    }
+    // Runs the input as one command, asking which template was meant if several match and `choice` is `None`
+    fn run_command(&mut self, input: &str, choice: Option<usize>) -> Result<(), Box<dyn Error>> {
+        let (sanitized_input, sanitize_map) = normalizer::sanitize(input);
+        let mut candidates = self.handler.find_candidates(sanitized_input.as_str())?;
+        if candidates.len() > 1 && choice.is_none() {
+            let options = candidates.iter().map(|c| self.handler.describe(c)).collect::<Result<Vec<_>, _>>()?;
+            self.speak(&format!("Did you mean {}?", Self::join_options(&options)))?;
+            self.pending_choice = Some(PendingChoice {
+                input: String::from(input),
+                options: options,
+                remaining: Vec::new(),
+            });
+            return Ok(());
+        }
+        // The templates may have been reloaded since the question was asked, in which case the first is run
+        let result = match choice {
+            Some(index) if index < candidates.len() => Some(candidates.swap_remove(index)),
+            _ => candidates.into_iter().next(),
+        };
         if let Some(the_match) = result {
             let func = the_match.function;
-            let inst = the_match.match_inst;
+            let inst = &the_match.match_inst;
+            for correction in inst.corrections() {
+                println!("Heard \"{}\" as \"{}\"", correction.heard, correction.corrected);
+            }
             let mut values = Vec::<CortexValue>::new();
             for i in 0..func.num_params() {
                 let param = func.get_param(i).unwrap();
                 let param_name = param;
-                if let Some(binding) = inst.get_binding(param_name) {
-                    values.push(CortexValue::String(binding.clone()));
+                if let Some(value) = the_match.raw_value(param_name, input, &sanitize_map) {
+                    values.push(Self::slot_to_cortex_value(&mut self.interpreter, param_name, &value, &the_match.param_types[i])?);
                 } else {
                     values.push(CortexValue::None);
                 }
             }
             let _return_val = self.interpreter.call_function(func, values)?;
         } else {
+            let suggestions = self.handler.suggestions(sanitized_input.as_str(), SUGGESTION_LIMIT)?;
+            if !suggestions.is_empty() {
+                println!("Did you mean: {}?", suggestions.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(", "));
+            }
             let fallback = self.handler.get_fallback()?;
             if let Some(func) = fallback {
-                let _return_val = self.interpreter.call_function(&func, vec![CortexValue::String(String::from(input))])?;
+                let mut values = vec![CortexValue::String(String::from(input))];
+                // The fallback may declare a second parameter to receive the suggestions
+                if func.num_params() > 1 {
+                    let param_name = func.get_param(1).unwrap();
+                    let param_type = &self.handler.get_fallback_param_types()[1];
+                    values.push(Self::slot_to_cortex_value(&mut self.interpreter, param_name, &SlotValue::List(suggestions), param_type)?);
+                }
+                let _return_val = self.interpreter.call_function(func, values)?;
             }
         }
         Ok(())
     }
 
+    // Says the text through the voice client, the same way `Voice.speak` does
+    fn speak(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        match &self.deepgram {
+            Some(deepgram) => block_on(deepgram.borrow().speak(text)),
+            None => {
+                println!("Response: {}", text);
+                Ok(())
+            },
+        }
+    }
+    // Joins options as "a or b", or "a, b or c"
+    fn join_options(options: &[String]) -> String {
+        match options.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+            Some((last, _)) => last.clone(),
+            None => String::new(),
+        }
+    }
+
+    /// Prints how each template fared against the input, to help work out why it did or did not match
+    pub fn explain(&mut self, input: &str) -> Result<(), Box<dyn Error>> {
+        self.reload_templates_if_changed();
+        self.handler.refresh_entities()?;
+        let (sanitized_input, _) = normalizer::sanitize(input);
+        println!("Explaining \"{}\"", sanitized_input);
+        for explanation in self.handler.explain(sanitized_input.as_str())? {
+            println!("{}", explanation);
+        }
+        Ok(())
+    }
+
+    fn slot_to_cortex_value(interpreter: &mut CortexInterpreter, param_name: &str, value: &SlotValue, param_type: &CortexType) -> Result<CortexValue, Box<dyn Error>> {
+        let param_type = param_type.clone().to_non_optional();
+        let invalid = || RunnerError::InvalidBinding(String::from(param_name), value.to_string());
+        let string_list = CortexType::list(CortexType::string());
+        if param_type == CortexType::string() {
+            Ok(CortexValue::String(value.to_string()))
+        } else if param_type == CortexType::number() {
+            match value {
+                SlotValue::Number(n) => Ok(CortexValue::Number(*n)),
+                SlotValue::Text(t) => Ok(slots::parse_number(t).map(CortexValue::Number).ok_or_else(invalid)?),
+                SlotValue::Boolean(_) | SlotValue::List(_) => Err(Box::new(invalid())),
+            }
+        } else if param_type == CortexType::boolean() {
+            match value {
+                SlotValue::Boolean(b) => Ok(CortexValue::Boolean(*b)),
+                SlotValue::Text(t) => Ok(slots::parse_bool(t).map(CortexValue::Boolean).ok_or_else(invalid)?),
+                SlotValue::Number(_) | SlotValue::List(_) => Err(Box::new(invalid())),
+            }
+        } else if param_type == CortexType::reference(string_list.clone(), false) || param_type == CortexType::reference(string_list, true) {
+            let items = match value {
+                SlotValue::List(items) => items.clone(),
+                SlotValue::Text(t) => slots::parse_list(t),
+                SlotValue::Number(_) | SlotValue::Boolean(_) => vec![value.to_string()],
+            };
+            // Lists live on the heap, so the list literal is evaluated to allocate it and get a reference to it
+            let list = PExpression::ListLiteral(items.into_iter().map(PExpression::String).collect());
+            Ok(interpreter.execute_expression(list)?)
+        } else {
+            Err(Box::new(RunnerError::InvalidParameterType(String::from(param_name))))
+        }
+    }
+
     fn register_modules(&mut self) -> Result<(), Box<dyn Error>> {
         self.interpreter.register_module(&PathIdent::simple(String::from("Debug")), Self::build_debug_module()?)?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Math")), Self::build_math_module()?)?;
@@ -190,7 +471,7 @@ impl CommandRunner {
         let spotify_module = Self::build_spotify_module(self.spotify.clone().unwrap())?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Spotify")), spotify_module)?;
 
-        let voice_module = Self::build_voice_module(self.deepgram.clone().unwrap())?;
+        let voice_module = Self::build_voice_module(self.deepgram.clone())?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Voice")), voice_module)?;
 
         let location_module = Self::build_location_module()?;
@@ -199,14 +480,18 @@ impl CommandRunner {
         let weather_module = Self::build_weather_module()?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Weather")), weather_module)?;
 
-        let memory_module = Self::build_memory_module(self.memory.clone().unwrap())?;
+        let memory_module = Self::build_memory_module(self.memory.clone())?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Memory")), memory_module)?;
 
-        let search_module = Self::build_search_module(self.search.clone().unwrap())?;
+        let search_module = Self::build_search_module(self.search.clone())?;
         self.interpreter.register_module(&PathIdent::simple(String::from("Search")), search_module)?;
 
         Ok(())
     }
+    // Services that need credentials are missing when the templates were only loaded, in which case using them fails
+    fn service<'a, T>(service: &'a Option<Rc<RefCell<T>>>, name: &'static str) -> Result<&'a Rc<RefCell<T>>, RunnerError> {
+        service.as_ref().ok_or(RunnerError::ServiceUnavailable(name))
+    }
     fn build_debug_module() -> Result<Module, Box<dyn Error>> {
         let mut module = Module::new();
         module.add_function(
@@ -351,7 +636,7 @@ impl CommandRunner {
         Ok(module)
     }
 
-    fn build_voice_module(deepgram: Rc<RefCell<DeepgramClient>>) -> Result<Module, Box<dyn Error>> {
+    fn build_voice_module(deepgram: Option<Rc<RefCell<DeepgramClient>>>) -> Result<Module, Box<dyn Error>> {
         let mut module = Module::new();
         let dg1 = deepgram.clone();
         module.add_function(
@@ -362,7 +647,7 @@ impl CommandRunner {
                 Body::Native(Box::new(move |env, _heap| {
                     let text = env.get_value("text")?;
                     if let CortexValue::String(string) = text {
-                        block_on(dg1.borrow().speak(&string))?;
+                        block_on(Self::service(&dg1, "Voice")?.borrow().speak(&string))?;
                     }
                     Ok(CortexValue::Void)
                 })),
@@ -516,7 +801,7 @@ impl CommandRunner {
         Ok(module)
     }
 
-    fn build_memory_module(memory: Rc<RefCell<Memory>>) -> Result<Module, Box<dyn Error>> {
+    fn build_memory_module(memory: Option<Rc<RefCell<Memory>>>) -> Result<Module, Box<dyn Error>> {
         let mut module = Module::new();
         let m1 = memory.clone();
         module.add_function(
@@ -527,7 +812,7 @@ impl CommandRunner {
                 Body::Native(Box::new(move |env, _heap| {
                     let key = env.get_value("key")?;
                     let key = unwrap_enum!(key, CortexValue::String(v) => v);
-                    let memory = m1.borrow().get(&key);
+                    let memory = Self::service(&m1, "Memory")?.borrow().get(&key);
                     if let Some(m) = memory {
                         if let MemoryValue::Single(s) = m {
                             Ok(CortexValue::String(s))
@@ -550,7 +835,7 @@ impl CommandRunner {
                 Body::Native(Box::new(move |env, heap| {
                     let key = env.get_value("key")?;
                     let key = unwrap_enum!(key, CortexValue::String(v) => v);
-                    let memory = m2.borrow().get(&key);
+                    let memory = Self::service(&m2, "Memory")?.borrow().get(&key);
                     if let Some(m) = memory {
                         if let MemoryValue::List(l) = m {
                             let list = CortexValue::List(l.into_iter().map(|s| CortexValue::String(s)).collect());
@@ -576,6 +861,7 @@ impl CommandRunner {
                 ],
                 CortexType::void(),
                 Body::Native(Box::new(move |env, heap| {
+                    let memory = Self::service(&m3, "Memory")?;
                     let key = env.get_value("key")?;
                     let key = unwrap_enum!(key, CortexValue::String(v) => v);
                     let value = env.get_value("value")?;
@@ -583,14 +869,14 @@ impl CommandRunner {
                         let ref_val = heap.get(addr);
                         if let CortexValue::List(ref items) = *ref_val.borrow() {
                             let value = items.iter().map(|v| to_string(v)).collect::<Vec<_>>();
-                            m3.borrow_mut().set(key, MemoryValue::List(value));
+                            memory.borrow_mut().set(key, MemoryValue::List(value));
                         } else {
-                            m3.borrow_mut().set(key, MemoryValue::Single(to_string(&*ref_val.borrow())));
+                            memory.borrow_mut().set(key, MemoryValue::Single(to_string(&*ref_val.borrow())));
                         };
                     } else {
-                        m3.borrow_mut().set(key, MemoryValue::Single(to_string(&value)));
+                        memory.borrow_mut().set(key, MemoryValue::Single(to_string(&value)));
                     }
-                    m3.borrow().save()?;
+                    memory.borrow().save()?;
                     
                     Ok(CortexValue::Void)
                 })),
@@ -601,7 +887,7 @@ impl CommandRunner {
         Ok(module)
     }
 
-    fn build_search_module(search: Rc<RefCell<WebSummarizer>>) -> Result<Module, Box<dyn Error>> {
+    fn build_search_module(search: Option<Rc<RefCell<WebSummarizer>>>) -> Result<Module, Box<dyn Error>> {
         let mut module = Module::new();
         let s1 = search.clone();
         module.add_function(
@@ -612,7 +898,7 @@ impl CommandRunner {
                 Body::Native(Box::new(move |env, _heap| {
                     let query_var = env.get_value("query")?;
                     let query = unwrap_enum!(query_var, CortexValue::String(v) => v);
-                    let result = block_on(s1.borrow().summarize_topic(&query))?;
+                    let result = block_on(Self::service(&s1, "Search")?.borrow().summarize_topic(&query))?;
                     Ok(CortexValue::String(result))
                 })),
                 vec![]
@@ -0,0 +1,25 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, time::SystemTime};
+
+/// Tracks the modification times of a set of files so that changes to them can be detected
+pub struct FileWatcher {
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        FileWatcher {
+            modified: paths.iter().map(|p| (p.clone(), Self::modified_time(p))).collect(),
+        }
+    }
+
+    /// Returns true if any watched file has been modified, created or deleted since the watcher was created
+    pub fn has_changed(&self) -> bool {
+        self.modified
+            .iter()
+            .any(|(path, modified)| Self::modified_time(path) != *modified)
+    }
+
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+}
@@ -0,0 +1,106 @@
+use super::normalizer::Normalizer;
+
+/// A named set of known values that an entity slot (`[room:{rooms}]`) is restricted to.
+/// Each value has a canonical form, which is what gets bound, and any number of synonyms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    values: Vec<EntityValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct EntityValue {
+    canonical: String,
+    synonyms: Vec<String>,
+}
+
+impl Entity {
+    pub fn new() -> Self {
+        Entity {
+            values: Vec::new(),
+        }
+    }
+
+    pub fn add_value(&mut self, canonical: &str, synonyms: &[&str]) {
+        self.values.push(EntityValue {
+            canonical: normalize(canonical),
+            synonyms: synonyms.iter().map(|s| normalize(s)).collect(),
+        });
+    }
+
+    /// Parses a line of an `% entity` block: either `canonical` or `canonical: synonym, synonym, ...`
+    pub fn add_line(&mut self, line: &str) {
+        match line.split_once(':') {
+            Some((canonical, synonyms)) => {
+                let synonyms: Vec<&str> = synonyms.split(',').filter(|s| !s.trim().is_empty()).collect();
+                self.add_value(canonical, &synonyms);
+            },
+            None => self.add_value(line, &[]),
+        }
+    }
+
+    /// Returns the canonical value that the text names, if it is one of the values or their synonyms
+    pub fn resolve(&self, text: &str) -> Option<&str> {
+        let text = normalize(text);
+        self.values
+            .iter()
+            .find(|v| v.canonical == text || v.synonyms.contains(&text))
+            .map(|v| v.canonical.as_str())
+    }
+
+    pub fn canonical_values(&self) -> impl Iterator<Item = &str> {
+        self.values.iter().map(|v| v.canonical.as_str())
+    }
+
+    /// Returns a copy in which every value can also be written in its normalized form. Canonical values are kept
+    /// as they are, since they are what gets bound.
+    pub fn normalized(&self, normalizer: &Normalizer) -> Entity {
+        let values = self.values
+            .iter()
+            .map(|v| {
+                let mut synonyms = v.synonyms.clone();
+                for phrase in std::iter::once(&v.canonical).chain(v.synonyms.iter()) {
+                    let normalized = normalizer.normalize(phrase);
+                    if normalized != *phrase && !synonyms.contains(&normalized) {
+                        synonyms.push(normalized);
+                    }
+                }
+                EntityValue {
+                    canonical: v.canonical.clone(),
+                    synonyms: synonyms,
+                }
+            })
+            .collect();
+        Entity {
+            values: values,
+        }
+    }
+
+    /// Returns the regex (without a capture group) that matches any value or synonym
+    pub fn regex(&self) -> String {
+        let mut phrases: Vec<&String> = self.phrases().collect();
+        if phrases.is_empty() {
+            // Nothing can match an entity with no values
+            return String::from("[^\\s\\S]");
+        }
+        // Longer phrases come first so that e.g. "living room" is not matched as "living"
+        phrases.sort_by_key(|p| std::cmp::Reverse(p.len()));
+        let alternatives: Vec<String> = phrases
+            .iter()
+            .map(|p| p.split(' ').map(regex::escape).collect::<Vec<_>>().join(r"\s+"))
+            .collect();
+        format!(r"(?:{})\b", alternatives.join("|"))
+    }
+
+    /// Every word used by the values and synonyms, which fuzzy matching may correct towards
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.phrases().flat_map(|p| p.split(' '))
+    }
+
+    fn phrases(&self) -> impl Iterator<Item = &String> {
+        self.values.iter().flat_map(|v| std::iter::once(&v.canonical).chain(v.synonyms.iter()))
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+use super::textmap::{self, TextMap};
+
+/// Controls how far a heard word may be from a template word and still be treated as that word
+#[derive(Debug, Clone)]
+pub struct FuzzyOptions {
+    /// Maximum edit distance, as a fraction of the longer word's length
+    pub max_distance: f64,
+    /// Also accept words that sound alike (e.g. "paws" for "pause")
+    pub phonetic: bool,
+    /// Words shorter than this are never corrected, since short words are too easily confused
+    pub min_word_length: usize,
+}
+impl Default for FuzzyOptions {
+    fn default() -> Self {
+        FuzzyOptions {
+            max_distance: 0.34,
+            phonetic: true,
+            min_word_length: 3,
+        }
+    }
+}
+
+/// A word (or run of words) in the input that was replaced by a word from the template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    pub heard: String,
+    pub corrected: String,
+    pub(crate) words: Range<usize>,
+}
+
+/// Finds the input words that are not in the vocabulary but are close to a word that is.
+/// Two adjacent words may also be corrected to one, as in "spotted fie" for "spotify".
+pub fn find_corrections(words: &[&str], vocabulary: &[String], options: &FuzzyOptions) -> Vec<Correction> {
+    let known = |w: &str| vocabulary.iter().any(|v| v == w);
+    let mut corrections = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if known(words[i]) {
+            i += 1;
+            continue;
+        }
+        if let Some(corrected) = closest_word(words[i], vocabulary, options) {
+            corrections.push(Correction {
+                heard: String::from(words[i]),
+                corrected: corrected,
+                words: i..i + 1,
+            });
+            i += 1;
+            continue;
+        }
+        if i + 1 < words.len() && !known(words[i + 1]) {
+            let merged = format!("{}{}", words[i], words[i + 1]);
+            if let Some(corrected) = closest_word(&merged, vocabulary, options) {
+                corrections.push(Correction {
+                    heard: format!("{} {}", words[i], words[i + 1]),
+                    corrected: corrected,
+                    words: i..i + 2,
+                });
+                i += 2;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    corrections
+}
+
+/// Rebuilds the input with the corrections applied, returning it along with the byte range of each correction in it
+/// and a map from the rebuilt text back to the input
+pub fn apply_corrections(input: &str, corrections: &[Correction]) -> (String, Vec<Range<usize>>, TextMap) {
+    let words = textmap::word_ranges(input);
+    let mut output = String::new();
+    let mut ranges = Vec::new();
+    let mut map = TextMap::new();
+    let mut i = 0;
+    while i < words.len() {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        let start = output.len();
+        if let Some(c) = corrections.iter().find(|c| c.words.start == i) {
+            output.push_str(&c.corrected);
+            ranges.push(start..output.len());
+            map.push(start..output.len(), words[i].start..words[c.words.end - 1].end);
+            i = c.words.end;
+        } else {
+            output.push_str(&input[words[i].clone()]);
+            map.push_copied(start..output.len(), words[i].start);
+            i += 1;
+        }
+    }
+    (output, ranges, map)
+}
+
+fn closest_word(word: &str, vocabulary: &[String], options: &FuzzyOptions) -> Option<String> {
+    if word.chars().count() < options.min_word_length {
+        return None;
+    }
+    let key = phonetic_key(word);
+    vocabulary
+        .iter()
+        .filter(|v| v.chars().count() >= options.min_word_length)
+        .filter_map(|v| {
+            let distance = levenshtein(word, v) as f64 / word.chars().count().max(v.chars().count()) as f64;
+            let sounds_alike = options.phonetic && key.len() >= 2 && key == phonetic_key(v);
+            if distance <= options.max_distance || sounds_alike {
+                Some((distance, v))
+            } else {
+                None
+            }
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, v)| v.clone())
+}
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(prev + cost);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// A rough key for how a word sounds: vowels after the first letter are dropped, consonants that are
+/// easily confused are merged, and repeats are collapsed
+pub fn phonetic_key(word: &str) -> String {
+    let mut key = String::new();
+    for (i, c) in word.to_lowercase().chars().enumerate() {
+        let mapped = match c {
+            'a' | 'e' | 'i' | 'o' | 'u' | 'y' if i == 0 => 'a',
+            'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'h' | 'w' => continue,
+            'b' | 'p' => 'p',
+            'd' | 't' => 't',
+            'f' | 'v' => 'f',
+            'c' | 'k' | 'q' | 'g' => 'k',
+            's' | 'z' | 'x' => 's',
+            'm' | 'n' => 'n',
+            c if c.is_alphabetic() => c,
+            _ => continue,
+        };
+        if !key.ends_with(mapped) {
+            key.push(mapped);
+        }
+    }
+    key
+}
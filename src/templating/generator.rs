@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use super::{matcher::{TemplateError, TemplateMatcher}, template::{Slot, SlotType, Symbol, SymbolInternal, Template}};
+
+/// Lists the concrete phrasings that a template accepts, with slots filled in by placeholder text
+pub struct ExampleGenerator<'a> {
+    matcher: &'a TemplateMatcher,
+    limit: usize,
+    fillers: HashMap<String, String>,
+}
+
+impl<'a> ExampleGenerator<'a> {
+    /// Creates a generator that produces at most `limit` phrasings per template
+    pub fn new(matcher: &'a TemplateMatcher, limit: usize) -> Self {
+        ExampleGenerator {
+            matcher: matcher,
+            limit: limit,
+            fillers: HashMap::new(),
+        }
+    }
+
+    /// Uses `text` for the slot with the given name instead of the default placeholder for its type
+    pub fn with_filler(mut self, slot_name: &str, text: &str) -> Self {
+        self.fillers.insert(String::from(slot_name), String::from(text));
+        self
+    }
+
+    /// Returns the distinct phrasings of the template, in the order the template lists its alternatives.
+    /// Optional symbols are included before they are left out.
+    pub fn generate(&self, template: &Template) -> Result<Vec<String>, TemplateError> {
+        self.generate_internal(template, &mut Vec::new(), &HashMap::new())
+    }
+    // `arguments` holds the phrasings of each argument of the subtemplate being expanded, by parameter name
+    fn generate_internal(&self, template: &Template, active: &mut Vec<String>, arguments: &HashMap<String, Vec<String>>) -> Result<Vec<String>, TemplateError> {
+        let mut phrases = Vec::new();
+        for clause in &template.clauses {
+            let mut partials = vec![String::new()];
+            for sym in &clause.symbols {
+                let options = self.symbol_phrases(sym, active, arguments)?;
+                partials = self.combine(&partials, &options);
+            }
+            for phrase in partials {
+                if phrases.len() >= self.limit {
+                    return Ok(phrases);
+                }
+                if !phrases.contains(&phrase) {
+                    phrases.push(phrase);
+                }
+            }
+        }
+        Ok(phrases)
+    }
+
+    fn symbol_phrases(&self, sym: &Symbol, active: &mut Vec<String>, arguments: &HashMap<String, Vec<String>>) -> Result<Vec<String>, TemplateError> {
+        let mut phrases = match &sym.symbol {
+            SymbolInternal::Text(t) => vec![t.to_lowercase()],
+            SymbolInternal::SubtemplateCall(name) => {
+                let subt = self.matcher.enter_subtemplate(name, active)?;
+                let phrases = self.generate_internal(subt, active, &HashMap::new())?;
+                active.pop();
+                phrases
+            },
+            SymbolInternal::ParameterizedCall(name, args) => {
+                let subtemplate_arguments = self.matcher
+                    .get_subtemplate_parameters(name)
+                    .iter()
+                    .cloned()
+                    .zip(args.iter().map(|arg| self.generate_internal(arg, active, arguments)))
+                    .map(|(param, phrases)| phrases.map(|p| (param, p)))
+                    .collect::<Result<HashMap<_, _>, TemplateError>>()?;
+                let subt = self.matcher.enter_subtemplate(name, active)?;
+                let phrases = self.generate_internal(subt, active, &subtemplate_arguments)?;
+                active.pop();
+                phrases
+            },
+            SymbolInternal::Parameter(name) => arguments.get(name).cloned().ok_or_else(|| TemplateError::UnknownParameter(name.clone()))?,
+            SymbolInternal::VarBind(slot) => vec![self.filler(slot)],
+            SymbolInternal::Wildcard => vec![String::new()],
+            SymbolInternal::Template(template) => self.generate_internal(template, active, arguments)?,
+        };
+        if sym.optional {
+            phrases.push(String::new());
+        }
+        Ok(phrases)
+    }
+
+    // Appends every option to every partial phrase, stopping once the limit is reached
+    fn combine(&self, partials: &[String], options: &[String]) -> Vec<String> {
+        let mut combined = Vec::new();
+        for partial in partials {
+            for option in options {
+                if combined.len() >= self.limit {
+                    return combined;
+                }
+                let phrase = match (partial.is_empty(), option.is_empty()) {
+                    (true, _) => option.clone(),
+                    (false, true) => partial.clone(),
+                    (false, false) => format!("{} {}", partial, option),
+                };
+                combined.push(phrase);
+            }
+        }
+        combined
+    }
+
+    fn filler(&self, slot: &Slot) -> String {
+        if let Some(text) = self.fillers.get(&slot.name) {
+            return text.clone();
+        }
+        match &slot.slot_type {
+            SlotType::Text | SlotType::List => slot.name.clone(),
+            SlotType::Entity(entity) => self.matcher
+                .get_entity(entity)
+                .and_then(|e| e.canonical_values().next())
+                .map_or_else(|| slot.name.clone(), String::from),
+            SlotType::Number => String::from("five"),
+            SlotType::Duration => String::from("five minutes"),
+            SlotType::Ordinal => String::from("first"),
+            SlotType::Bool => String::from("yes"),
+        }
+    }
+}
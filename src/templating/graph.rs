@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+
+use super::{matcher::TemplateMatcher, template::{Symbol, SymbolInternal, Template}, validation};
+
+/// Writes the templates and the subtemplates they use as a Graphviz DOT graph. Templates are boxes and subtemplates
+/// are ellipses, with an edge for every call. Alternations and slots get their own nodes under whatever contains them.
+/// Subtemplates that no template reaches are drawn dashed and grey, and calls to undefined subtemplates are red.
+pub fn to_dot<'a>(templates: impl Iterator<Item = (&'a str, &'a Template)>, matcher: &'a TemplateMatcher) -> String {
+    let templates: Vec<(&str, &Template)> = templates.collect();
+    let mut names: Vec<&String> = matcher.subtemplate_names().collect();
+    names.sort();
+    let dead = dead_subtemplates(templates.iter().map(|(_, t)| *t), matcher);
+
+    let mut graph = DotGraph {
+        lines: Vec::new(),
+        next_id: 0,
+        subtemplates: names.clone(),
+    };
+    for (i, (source, template)) in templates.iter().enumerate() {
+        let id = format!("t{}", i);
+        graph.node(&id, source, "shape=box");
+        graph.template(&id, template);
+    }
+    for (i, name) in names.iter().enumerate() {
+        let id = format!("s{}", i);
+        let mut label = format!("{{{}}}", name);
+        let parameters = matcher.get_subtemplate_parameters(name);
+        if !parameters.is_empty() {
+            label = format!("{{{}({})}}", name, parameters.join(", "));
+        }
+        let style = if dead.contains(name) { "shape=ellipse, style=dashed, color=grey" } else { "shape=ellipse" };
+        graph.node(&id, &label, style);
+        if let Some(subtemplate) = matcher.get_subtemplate(name) {
+            graph.template(&id, subtemplate);
+        }
+    }
+
+    let mut dot = String::from("digraph templates {\n    rankdir=LR;\n");
+    for line in graph.lines {
+        dot.push_str("    ");
+        dot.push_str(&line);
+        dot.push('\n');
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Returns the names of the subtemplates that none of the templates call, directly or through other subtemplates
+pub fn dead_subtemplates<'a>(templates: impl Iterator<Item = &'a Template>, matcher: &'a TemplateMatcher) -> Vec<&'a String> {
+    let mut reached = HashSet::new();
+    let mut pending: Vec<&String> = templates.flat_map(validation::subtemplate_calls).collect();
+    while let Some(name) = pending.pop() {
+        if reached.insert(name) {
+            if let Some(subtemplate) = matcher.get_subtemplate(name) {
+                pending.extend(validation::subtemplate_calls(subtemplate));
+            }
+        }
+    }
+    let mut dead: Vec<&String> = matcher.subtemplate_names().filter(|n| !reached.contains(n)).collect();
+    dead.sort();
+    dead
+}
+
+struct DotGraph<'a> {
+    lines: Vec<String>,
+    next_id: usize,
+    subtemplates: Vec<&'a String>,
+}
+impl DotGraph<'_> {
+    fn template(&mut self, parent: &str, template: &Template) {
+        for symbol in template.clauses.iter().flat_map(|c| c.symbols.iter()) {
+            self.symbol(parent, symbol);
+        }
+    }
+
+    fn symbol(&mut self, parent: &str, symbol: &Symbol) {
+        let edge_style = if symbol.optional { "style=dashed" } else { "" };
+        match &symbol.symbol {
+            SymbolInternal::SubtemplateCall(name) => self.call(parent, name, edge_style),
+            SymbolInternal::ParameterizedCall(name, args) => {
+                self.call(parent, name, edge_style);
+                // Arguments are matched where they are written, so their own calls and slots belong to the caller
+                for arg in args {
+                    self.template(parent, arg);
+                }
+            },
+            SymbolInternal::VarBind(slot) => {
+                let id = self.next_node(parent);
+                self.node(&id, &slot.to_string(), "shape=note");
+                self.edge(parent, &id, edge_style);
+            },
+            SymbolInternal::Template(template) if template.clauses.len() > 1 || symbol.label.is_some() => {
+                let id = self.next_node(parent);
+                self.node(&id, &symbol.to_string(), "shape=diamond");
+                self.edge(parent, &id, edge_style);
+                self.template(&id, template);
+            },
+            SymbolInternal::Template(template) => self.template(parent, template),
+            SymbolInternal::Text(_) | SymbolInternal::Parameter(_) | SymbolInternal::Wildcard => (),
+        }
+    }
+
+    fn call(&mut self, parent: &str, name: &String, edge_style: &str) {
+        match self.subtemplates.iter().position(|n| *n == name) {
+            Some(index) => self.edge(parent, &format!("s{}", index), edge_style),
+            None => {
+                let id = self.next_node(parent);
+                self.node(&id, &format!("{{{}}} (undefined)", name), "shape=ellipse, color=red");
+                self.edge(parent, &id, edge_style);
+            },
+        }
+    }
+
+    fn next_node(&mut self, parent: &str) -> String {
+        self.next_id += 1;
+        format!("{}_{}", parent, self.next_id)
+    }
+
+    fn node(&mut self, id: &str, label: &str, attributes: &str) {
+        self.lines.push(format!("{} [label=\"{}\", {}];", id, escape(label), attributes));
+    }
+
+    fn edge(&mut self, from: &str, to: &str, attributes: &str) {
+        if attributes.is_empty() {
+            self.lines.push(format!("{} -> {};", from, to));
+        } else {
+            self.lines.push(format!("{} -> {} [{}];", from, to, attributes));
+        }
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
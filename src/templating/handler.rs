@@ -1,9 +1,22 @@
-use std::{error::Error, fs::File, io::{BufRead, BufReader}};
+use std::{cmp::Ordering, collections::HashSet, error::Error, fmt::Display, fs::{self, File}, io::{BufRead, BufReader, Lines}, iter::Peekable, ops::Range, path::{Path, PathBuf}};
 
-use cortex_lang::{interpreting::interpreter::CortexInterpreter, parsing::parser::CortexParser, preprocessing::ast::function::RFunction};
+use cortex_lang::{interpreting::interpreter::CortexInterpreter, parsing::{ast::{r#type::CortexType, top_level::PFunction}, parser::{CortexParser, ParseError as CortexParseError}}, preprocessing::ast::function::RFunction};
+use regex::Regex;
 use thiserror::Error;
 
-use super::{matcher::{Match, TemplateMatcher}, parser::TemplateParser, template::Template};
+use super::{entity::Entity, fuzzy::FuzzyOptions, generator::ExampleGenerator, graph, normalizer::{self, Normalizer}, matcher::{CompiledTemplate, CompiledTemplateSet, Match, TemplateError, TemplateMatcher}, parser::{ParseError, TemplateParser}, slots::{self, SlotValue}, template::Template, textmap::{self, TextMap}, validation::{self, ExampleFailure, ValidationIssue}};
+
+pub const TEMPLATE_FILE_EXTENSION: &str = "tmpl";
+
+// The words that join the commands of a compound utterance, as in "pause the music and then tell me the weather".
+// Longer conjunctions come first so that "and then" is preferred over "and".
+const CONJUNCTIONS: [&[&str]; 4] = [&["and", "then"], &["after", "that"], &["and"], &["then"]];
+
+// How many phrasings of each template are compared against an unmatched input when making suggestions
+const SUGGESTION_EXAMPLE_LIMIT: usize = 50;
+
+/// Looks up the values of an entity declared with `% entity <name> memory=<key>`, given the key
+pub type EntityResolver = Box<dyn Fn(&str) -> Option<Vec<String>>>;
 
 #[derive(Error, Debug)]
 pub enum TemplateHandlerError {
@@ -11,12 +24,43 @@ pub enum TemplateHandlerError {
     IllegalLine(String),
     #[error("Unexpected end of input (while {0})")]
     UnexpectedEof(&'static str),
+    #[error("Invalid template attribute: {0}")]
+    InvalidAttribute(String),
+    #[error("Invalid subtemplate name (expected \"name\" or \"name(param, ...)\"): {0}")]
+    InvalidSubtemplateName(String),
+    #[error("Invalid example (expected \"% example <utterance> => name=value, ...\"): {0}")]
+    InvalidExample(String),
+    #[error("Template files include each other in a cycle: {}", .0.join(" -> "))]
+    IncludeCycle(Vec<String>),
+    #[error("Template file is invalid:\n{}", .0.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<ValidationIssue>),
+    #[error(
+        "{path}:{line}{}{}: {error}",
+        .column.map(|c| format!(":{}", c)).unwrap_or_default(),
+        .block.map(|b| format!(" (in {} block)", b)).unwrap_or_default(),
+    )]
+    Located {
+        path: String,
+        line: usize,
+        column: Option<usize>,
+        block: Option<&'static str>,
+        error: Box<dyn Error>,
+    },
 }
 
 pub struct TemplateHandler {
     matcher: TemplateMatcher,
     templates: Vec<TemplateEntry>,
+    compiled: CompiledTemplateSet,
+    // The compiled `% except` templates of each template, by template index
+    exceptions: Vec<Vec<CompiledTemplate>>,
     fallback: Option<RFunction>,
+    fallback_param_types: Vec<CortexType>,
+    fuzzy: Option<FuzzyOptions>,
+    sources: Vec<PathBuf>,
+    entity_resolver: Option<EntityResolver>,
+    memory_entities: Vec<MemoryEntity>,
+    disabled_tags: HashSet<String>,
 }
 
 impl TemplateHandler {
@@ -24,102 +68,975 @@ impl TemplateHandler {
         TemplateHandler {
             matcher: TemplateMatcher::new(),
             templates: Vec::new(),
+            compiled: CompiledTemplateSet::empty(),
+            exceptions: Vec::new(),
             fallback: None,
+            fallback_param_types: Vec::new(),
+            fuzzy: None,
+            sources: Vec::new(),
+            entity_resolver: None,
+            memory_entities: Vec::new(),
+            disabled_tags: HashSet::new(),
         }
     }
 
+    /// Enables fuzzy matching, which is tried when no template matches the input exactly
+    pub fn set_fuzzy(&mut self, options: Option<FuzzyOptions>) {
+        self.fuzzy = options;
+    }
+    pub fn get_fuzzy(&self) -> Option<&FuzzyOptions> {
+        self.fuzzy.as_ref()
+    }
+
+    /// Sets how template text and input are normalized before matching, recompiling any loaded templates
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) -> Result<(), Box<dyn Error>> {
+        self.matcher.set_normalizer(normalizer);
+        if !self.templates.is_empty() {
+            self.compile()?;
+        }
+        Ok(())
+    }
+    pub fn get_normalizer(&self) -> &Normalizer {
+        self.matcher.get_normalizer()
+    }
+
+    /// Sets where the values of entities declared with a `memory=<key>` attribute come from. These values are
+    /// read while loading, so this must be set before the templates are loaded, and again by `refresh_entities`.
+    pub fn set_entity_resolver(&mut self, resolver: Option<EntityResolver>) {
+        self.entity_resolver = resolver;
+    }
+    /// Reads the values of entities declared with `memory=<key>` again, recompiling the templates if any of them
+    /// have changed since they were last read. Returns whether anything changed.
+    pub fn refresh_entities(&mut self) -> Result<bool, Box<dyn Error>> {
+        let Some(resolve) = &self.entity_resolver else {
+            return Ok(false);
+        };
+        let mut changed = false;
+        for memory_entity in &mut self.memory_entities {
+            let remembered = resolve(&memory_entity.key);
+            if remembered != memory_entity.remembered {
+                memory_entity.remembered = remembered;
+                self.matcher.add_entity(&memory_entity.name, memory_entity.entity());
+                changed = true;
+            }
+        }
+        if changed {
+            self.compile()?;
+        }
+        Ok(changed)
+    }
+
+    /// The files (and directories) that templates were loaded from, including any that were included
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Turns every template tagged with `tag` (`% temp tags=<tag>`) on or off. A template with several tags is
+    /// only used while all of them are enabled. Tags are enabled until disabled here.
+    pub fn set_tag_enabled(&mut self, tag: &str, enabled: bool) {
+        if enabled {
+            self.disabled_tags.remove(tag);
+        } else {
+            self.disabled_tags.insert(String::from(tag));
+        }
+    }
+    pub fn is_tag_enabled(&self, tag: &str) -> bool {
+        !self.disabled_tags.contains(tag)
+    }
+    pub fn disabled_tags(&self) -> impl Iterator<Item = &String> {
+        self.disabled_tags.iter()
+    }
+    // Whether the template can currently be chosen, given its `enabled` attribute and its tags
+    fn is_active(&self, entry: &TemplateEntry) -> bool {
+        entry.enabled && entry.tags.iter().all(|tag| self.is_tag_enabled(tag))
+    }
+
+    /// Finds the best-scoring template that matches the input. When several score equally,
+    /// the one that appears first in the template file wins.
     pub fn find_function<'a>(&'a self, input: &str) -> Result<Option<MatchResult<'a>>, Box<dyn Error>> {
-        for entry in &self.templates {
-            let result = self.matcher.try_match(input, &entry.template)?;
-            if let Some(mmatch) = result {
-                let func = &entry.function;
-                return Ok(Some(MatchResult {
-                    function: func,
-                    match_inst: mmatch,
-                }));
+        Ok(self.find_candidates(input)?.into_iter().next())
+    }
+    /// Finds every template that matches the input with the best score, in file order
+    pub fn find_candidates<'a>(&'a self, input: &str) -> Result<Vec<MatchResult<'a>>, Box<dyn Error>> {
+        let (normalized, input_map) = self.matcher.get_normalizer().normalize_mapped(input);
+        let candidates = self.find_tied_normalized(&normalized).into_iter().map(|(index, mut mmatch, score)| {
+            let entry = &self.templates[index];
+            let compiled = self.compiled.get(index).unwrap();
+            compiled.restore_original(&mut mmatch, input, &input_map);
+            MatchResult {
+                function: &entry.function,
+                param_types: &entry.param_types,
+                match_inst: mmatch,
+                score: score,
+                input_map: input_map.clone(),
+                template: &entry.template,
+                compiled: compiled,
+            }
+        });
+        Ok(candidates.collect())
+    }
+    // Returns the index of the best-scoring template along with its match
+    fn find_best(&self, input: &str) -> Option<(usize, Match, MatchScore)> {
+        let (normalized, map) = self.matcher.get_normalizer().normalize_mapped(input);
+        let (index, mut mmatch, score) = self.find_tied_normalized(&normalized).into_iter().next()?;
+        self.compiled.get(index).unwrap().restore_original(&mut mmatch, input, &map);
+        Some((index, mmatch, score))
+    }
+    // Returns every template that matches with the best score, in file order
+    fn find_tied_normalized(&self, input: &str) -> Vec<(usize, Match, MatchScore)> {
+        let mut best = Vec::new();
+        for index in self.compiled.candidates(input) {
+            if !self.is_active(&self.templates[index]) {
+                continue;
+            }
+            let compiled = self.compiled.get(index).unwrap();
+            if let Some(mmatch) = compiled.try_match(input).filter(|_| !self.is_excluded(index, input)) {
+                self.keep_best(&mut best, index, mmatch);
+            }
+        }
+        if let (true, Some(options)) = (best.is_empty(), &self.fuzzy) {
+            for index in 0..self.templates.len() {
+                if !self.is_active(&self.templates[index]) {
+                    continue;
+                }
+                let compiled = self.compiled.get(index).unwrap();
+                if let Some(mmatch) = compiled.try_fuzzy_match(input, options).filter(|_| !self.is_excluded(index, input)) {
+                    self.keep_best(&mut best, index, mmatch);
+                }
+            }
+        }
+        best
+    }
+    // Whether the input matches one of the template's `% except` lines, which rules the template out
+    fn is_excluded(&self, index: usize, input: &str) -> bool {
+        self.exceptions[index].iter().any(|e| e.try_match(input).is_some())
+    }
+    fn keep_best(&self, best: &mut Vec<(usize, Match, MatchScore)>, index: usize, mmatch: Match) {
+        let score = MatchScore {
+            priority: self.templates[index].priority,
+            corrections: mmatch.corrections().len(),
+            literal_words: mmatch.literal_words(),
+            wildcard_chars: mmatch.wildcard_chars(),
+        };
+        match best.first().map(|(_, _, b)| score.cmp(b)) {
+            None | Some(Ordering::Greater) => *best = vec![(index, mmatch, score)],
+            Some(Ordering::Equal) => best.push((index, mmatch, score)),
+            Some(Ordering::Less) => (),
+        }
+    }
+
+    /// A short phrasing of the matched template, with each slot written as its name
+    pub fn describe(&self, result: &MatchResult) -> Result<String, TemplateError> {
+        let mut generator = ExampleGenerator::new(&self.matcher, 1);
+        for name in self.matcher.slot_names(result.template)? {
+            generator = generator.with_filler(&name, &name);
+        }
+        Ok(generator.generate(result.template)?.into_iter().next().unwrap_or_default())
+    }
+
+    /// Which of the `describe` options an answer picks, by position or by words only that option contains
+    pub fn choose(&self, answer: &str, options: &[String]) -> Option<usize> {
+        let normalizer = self.matcher.get_normalizer();
+        let answer = normalizer.normalize(answer).to_lowercase();
+        for word in answer.split_whitespace() {
+            let position = match word {
+                "last" => options.len().checked_sub(1),
+                word => slots::parse_ordinal(word).filter(|n| *n >= 1.0).map(|n| n as usize - 1),
+            };
+            if let Some(position) = position.filter(|p| *p < options.len()) {
+                return Some(position);
+            }
+        }
+
+        let answer_words = Self::word_set(&answer);
+        let option_words: Vec<HashSet<String>> = options.iter().map(|o| Self::word_set(&normalizer.normalize(o))).collect();
+        // Words that every option shares (such as "play" in "play music or play podcast") say nothing about the choice
+        let shared: Vec<usize> = option_words
+            .iter()
+            .map(|words| {
+                answer_words
+                    .iter()
+                    .filter(|w| words.contains(*w) && !option_words.iter().all(|o| o.contains(*w)))
+                    .count()
+            })
+            .collect();
+        let most = *shared.iter().max()?;
+        match shared.iter().filter(|s| **s == most).count() {
+            1 if most > 0 => shared.iter().position(|s| *s == most),
+            _ => None,
+        }
+    }
+
+    /// Splits an utterance that joins several commands with "and", "then", "and then" or "after that" into the
+    /// byte ranges of those commands within the input, if each of them matches a template on its own. When the
+    /// input can be split in more than one way, the split into the most commands is chosen. Returns `None` if
+    /// the input cannot be split into at least two commands.
+    pub fn split_compound(&self, input: &str) -> Option<Vec<Range<usize>>> {
+        let words = textmap::word_ranges(input);
+        let lowercase: Vec<String> = words.iter().map(|w| input[w.clone()].to_lowercase()).collect();
+        // The conjunctions found, as ranges of word indices
+        let mut boundaries = Vec::new();
+        for start in 0..words.len() {
+            for conjunction in CONJUNCTIONS {
+                let end = start + conjunction.len();
+                if end <= words.len() && lowercase[start..end].iter().zip(conjunction.iter()).all(|(w, c)| w == c) {
+                    boundaries.push(start..end);
+                }
             }
         }
-        Ok(None)
+        if boundaries.is_empty() {
+            return None;
+        }
+
+        // Each command is held as the indices of its first word and the word after its last
+        let matches = |(first, end): (usize, usize)| {
+            first < end && self.find_best(&input[words[first].start..words[end - 1].end]).is_some()
+        };
+        // The best split of the words from each index to the end, working backwards from the end
+        let mut best: Vec<Option<Vec<(usize, usize)>>> = vec![None; words.len() + 1];
+        for start in (0..words.len()).rev() {
+            let mut candidates = Vec::new();
+            if matches((start, words.len())) {
+                candidates.push(vec![(start, words.len())]);
+            }
+            for boundary in boundaries.iter().filter(|b| b.start > start) {
+                if let (true, Some(rest)) = (matches((start, boundary.start)), &best[boundary.end]) {
+                    let mut split = vec![(start, boundary.start)];
+                    split.extend(rest.iter().copied());
+                    candidates.push(split);
+                }
+            }
+            best[start] = candidates.into_iter().reduce(|a, b| if b.len() > a.len() { b } else { a });
+        }
+        best[0]
+            .take()
+            .filter(|split| split.len() > 1)
+            .map(|split| split.into_iter().map(|(first, end)| words[first].start..words[end - 1].end).collect())
+    }
+
+    /// How every template fared against the input, in file order
+    pub fn explain(&self, input: &str) -> Result<Vec<TemplateExplanation>, Box<dyn Error>> {
+        let selected = self.find_best(input).map(|(index, _, _)| index);
+        let input = &self.matcher.get_normalizer().normalize(input);
+        let mut explanations = Vec::new();
+        for (index, entry) in self.templates.iter().enumerate() {
+            let compiled = self.compiled.get(index).unwrap();
+            let mmatch = match &self.fuzzy {
+                Some(options) => compiled.try_fuzzy_match(input, options),
+                None => compiled.try_match(input),
+            };
+            let mut bindings = Vec::new();
+            if let Some(mmatch) = &mmatch {
+                for name in self.matcher.slot_names(&entry.template)? {
+                    if bindings.iter().any(|(n, _)| *n == name) {
+                        continue;
+                    }
+                    if let Some(value) = mmatch.get_value(&name) {
+                        bindings.push((name, value.to_string()));
+                    }
+                }
+            }
+            let matched_prefix = match mmatch {
+                Some(_) => None,
+                None => {
+                    let length = self.matcher.longest_prefix(input, &entry.template)?;
+                    Some(String::from(&input[..length]))
+                },
+            };
+            explanations.push(TemplateExplanation {
+                source: entry.source.clone(),
+                regex: String::from(compiled.as_str()),
+                selected: selected == Some(index),
+                enabled: self.is_active(entry),
+                excluded: self.is_excluded(index, input),
+                bindings: bindings,
+                matched_prefix: matched_prefix,
+            });
+        }
+        Ok(explanations)
+    }
+
+    /// Lists up to `limit` example phrasings for every template, alongside the template's source
+    pub fn examples(&self, limit: usize) -> Result<Vec<(&str, Vec<String>)>, TemplateError> {
+        let generator = ExampleGenerator::new(&self.matcher, limit);
+        self.templates
+            .iter()
+            .map(|entry| Ok((entry.source.as_str(), generator.generate(&entry.template)?)))
+            .collect()
+    }
+
+    /// Suggests up to `limit` phrasings for an input that no template matched, closest first. Each enabled template's
+    /// example phrasings are scored by how many words they share with the input (relative to the words in either),
+    /// and the best-scoring phrasing of each template that shares any words is suggested.
+    pub fn suggestions(&self, input: &str, limit: usize) -> Result<Vec<String>, TemplateError> {
+        let normalizer = self.matcher.get_normalizer();
+        let input_words = Self::word_set(&normalizer.normalize(input));
+        let generator = ExampleGenerator::new(&self.matcher, SUGGESTION_EXAMPLE_LIMIT);
+        let mut scored = Vec::new();
+        for entry in self.templates.iter().filter(|e| self.is_active(e)) {
+            let mut best: Option<(f64, String)> = None;
+            for phrase in generator.generate(&entry.template)? {
+                let phrase_words = Self::word_set(&normalizer.normalize(&phrase));
+                let shared = input_words.intersection(&phrase_words).count();
+                let total = input_words.union(&phrase_words).count();
+                if shared == 0 {
+                    continue;
+                }
+                let score = shared as f64 / total as f64;
+                if best.as_ref().is_none_or(|(b, _)| score > *b) {
+                    best = Some((score, phrase));
+                }
+            }
+            scored.extend(best);
+        }
+        // Sorting is stable, so templates that score equally stay in file order
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(scored.into_iter().map(|(_, phrase)| phrase).take(limit).collect())
+    }
+    fn word_set(text: &str) -> HashSet<String> {
+        text.split_whitespace().map(|w| w.to_lowercase()).collect()
+    }
+
+    /// Writes the templates and subtemplates as a Graphviz DOT graph, showing which subtemplates each one uses
+    pub fn to_dot(&self) -> String {
+        graph::to_dot(self.templates.iter().map(|e| (e.source.as_str(), &e.template)), &self.matcher)
+    }
+
+    /// Runs every `% example` and `% nonexample` through `find_function`, sanitized the way the runner sanitizes
+    /// transcripts, returning the ones that failed.
+    /// An example fails if its template is not the one chosen or binds different values; a non-example
+    /// fails if its template is chosen. Examples of disabled templates are skipped.
+    pub fn check(&self) -> Vec<ExampleFailure> {
+        let mut failures = Vec::new();
+        for (index, entry) in self.templates.iter().enumerate() {
+            if !self.is_active(entry) {
+                continue;
+            }
+            for example in &entry.examples {
+                let location = example.location.clone();
+                let utterance = example.utterance.clone();
+                let (input, _) = normalizer::sanitize(&example.utterance);
+                match (example.should_match, self.find_best(&input)) {
+                    (true, None) => failures.push(ExampleFailure::NoMatch(location, utterance)),
+                    (true, Some((chosen, _, _))) if chosen != index => {
+                        failures.push(ExampleFailure::WrongTemplate(location, utterance, self.templates[chosen].source.clone()));
+                    },
+                    (true, Some((_, mmatch, _))) => {
+                        for (name, expected) in &example.bindings {
+                            // Typed slots may be written either as heard or as their converted value
+                            let heard = mmatch.get_binding(name).cloned();
+                            let value = mmatch.get_value(name).map(|v| v.to_string());
+                            let as_heard = heard.as_ref().is_some_and(|h| h == expected || *h == normalizer::sanitize(expected).0);
+                            if !as_heard && value.as_ref() != Some(expected) {
+                                let actual = value.or(heard).unwrap_or_default();
+                                failures.push(ExampleFailure::WrongBinding(location.clone(), utterance.clone(), name.clone(), actual, expected.clone()));
+                            }
+                        }
+                    },
+                    (false, Some((chosen, _, _))) if chosen == index => failures.push(ExampleFailure::UnexpectedMatch(location, utterance)),
+                    (false, _) => (),
+                }
+            }
+        }
+        failures
     }
 
     pub fn get_fallback(&self) -> Result<Option<&RFunction>, Box<dyn Error>> {
         Ok(self.fallback.as_ref())
     }
+    /// The declared types of the fallback function's parameters: the input, then optionally the suggestions
+    pub fn get_fallback_param_types(&self) -> &Vec<CortexType> {
+        &self.fallback_param_types
+    }
+
+    /// Loads a template file, or every template file in a directory
+    pub fn load(&mut self, path: &str, interpreter: &mut CortexInterpreter) -> Result<(), Box<dyn Error>> {
+        if Path::new(path).is_dir() {
+            self.load_from_dir(path, interpreter)
+        } else {
+            self.load_from_file(path, interpreter)
+        }
+    }
 
     pub fn load_from_file(&mut self, filepath: &str, interpreter: &mut CortexInterpreter) -> Result<(), Box<dyn Error>> {
-        let file = File::open(filepath)?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines().peekable();
-        while let Some(_) = lines.peek() {
-            self.load_next_thing(&mut lines, interpreter)?;
+        let mut context = LoadContext::new();
+        if let Some(reader) = LineReader::open(Path::new(filepath), &context)? {
+            self.load_lines(reader, interpreter, &mut context)?;
+        }
+        self.finish_loading(context)
+    }
+
+    /// Loads every `*.tmpl` file in the directory (in name order). Subtemplates are shared between all of them.
+    pub fn load_from_dir(&mut self, dirpath: &str, interpreter: &mut CortexInterpreter) -> Result<(), Box<dyn Error>> {
+        let mut paths = fs::read_dir(dirpath)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext == TEMPLATE_FILE_EXTENSION));
+        paths.sort();
+
+        // The directory itself is a source so that adding or removing files counts as a change
+        self.sources.push(Path::new(dirpath).to_path_buf());
+        let mut context = LoadContext::new();
+        for path in paths {
+            if let Some(reader) = LineReader::open(&path, &context)? {
+                self.load_lines(reader, interpreter, &mut context)?;
+            }
+        }
+        self.finish_loading(context)
+    }
+
+    fn load_lines(&mut self, mut reader: LineReader, interpreter: &mut CortexInterpreter, context: &mut LoadContext) -> Result<(), Box<dyn Error>> {
+        context.include_stack.push(reader.canonical_path.clone());
+        context.loaded.insert(reader.canonical_path.clone());
+        self.sources.push(reader.canonical_path.clone());
+        while reader.has_more() {
+            self.load_next_thing(&mut reader, interpreter, context)?;
+        }
+        context.include_stack.pop();
+        Ok(())
+    }
+
+    fn finish_loading(&mut self, mut context: LoadContext) -> Result<(), Box<dyn Error>> {
+        context.issues.extend(self.validate()?);
+        if !context.issues.is_empty() {
+            return Err(Box::new(TemplateHandlerError::Invalid(context.issues)));
         }
         Ok(())
     }
 
-    fn load_next_thing(&mut self, iter: &mut dyn Iterator<Item = Result<String, std::io::Error>>, interpreter: &mut CortexInterpreter) -> Result<(), Box<dyn Error>> {
+    // Checks the loaded templates as a whole and compiles them if they are usable
+    fn validate(&mut self) -> Result<Vec<ValidationIssue>, Box<dyn Error>> {
+        let templates = || self.templates.iter().flat_map(|e| std::iter::once(&e.template).chain(e.exceptions.iter()));
+        let mut issues = validation::find_undefined_subtemplates(templates(), &self.matcher);
+        issues.extend(validation::find_undefined_entities(templates(), &self.matcher));
+        issues.extend(validation::find_subtemplate_cycles(&self.matcher));
+        if !issues.is_empty() {
+            // Templates can't be expanded until these are fixed
+            return Ok(issues);
+        }
+        for entry in &self.templates {
+            issues.extend(self.check_bindings(entry)?);
+        }
+        self.compile()?;
+        issues.extend(self.find_shadowed_templates());
+        Ok(issues)
+    }
+    fn check_bindings(&self, entry: &TemplateEntry) -> Result<Vec<ValidationIssue>, TemplateError> {
+        let mut slots = self.matcher.slot_names(&entry.template)?;
+        slots.sort();
+        slots.dedup();
+        let params: Vec<&String> = (0..entry.function.num_params())
+            .map(|i| entry.function.get_param(i).unwrap())
+            .collect();
+
+        let mut issues = Vec::new();
+        for slot in &slots {
+            if !params.contains(&slot) {
+                issues.push(ValidationIssue::UnknownBinding(entry.source.clone(), slot.clone()));
+            }
+        }
+        for (param, param_type) in params.iter().zip(entry.param_types.iter()) {
+            if !param_type.optional() && !slots.contains(param) {
+                issues.push(ValidationIssue::UnboundParameter(entry.source.clone(), (*param).clone()));
+            }
+        }
+        Ok(issues)
+    }
+    // A template is shadowed when an earlier one accepts exactly the same inputs, since it would always tie
+    // with (or beat) the later one. Slot names don't affect what is accepted, so they are ignored. An earlier
+    // template that can be turned off while the later one stays on (through a tag the later one lacks) doesn't
+    // shadow it, and neither does one with `% except` lines, since the inputs they rule out go to later templates.
+    fn find_shadowed_templates(&self) -> Vec<ValidationIssue> {
+        let group_names = Regex::new(r"\(\?<\w+>").unwrap();
+        let shapes: Vec<String> = (0..self.templates.len())
+            .map(|i| group_names.replace_all(self.compiled.get(i).unwrap().as_str(), "(").into_owned())
+            .collect();
+        let mut issues = Vec::new();
+        for (i, entry) in self.templates.iter().enumerate() {
+            let shadower = self.templates[..i]
+                .iter()
+                .enumerate()
+                .find(|(j, earlier)| {
+                    earlier.priority >= entry.priority
+                        && earlier.exceptions.is_empty()
+                        && earlier.enabled
+                        && earlier.tags.iter().all(|t| entry.tags.contains(t))
+                        && shapes[*j] == shapes[i]
+                });
+            if let Some((_, earlier)) = shadower {
+                issues.push(ValidationIssue::Shadowed(entry.source.clone(), earlier.source.clone()));
+            }
+        }
+        issues
+    }
+
+    // Subtemplates may be defined anywhere in the file, so templates are only compiled once everything is loaded
+    fn compile(&mut self) -> Result<(), Box<dyn Error>> {
+        let compiled = self.templates
+            .iter()
+            .map(|entry| self.matcher.compile(&entry.template))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.compiled = CompiledTemplateSet::new(compiled)?;
+        self.exceptions = self.templates
+            .iter()
+            .map(|entry| entry.exceptions.iter().map(|e| self.matcher.compile(e)).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    fn load_next_thing(&mut self, reader: &mut LineReader, interpreter: &mut CortexInterpreter, context: &mut LoadContext) -> Result<(), Box<dyn Error>> {
         loop {
-            let mut line = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("loading next element"))??;
+            let line = reader.next_line(None, "loading next element")?;
             if line.trim().is_empty() {
                 break;
             }
 
-            if line.starts_with("% temp") {
-                let template_line = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("reading template header"))??;
-
-                let mut function_lines = Vec::new();
-                line = String::new();
-                while !line.starts_with("% end") {
-                    function_lines.push(line.clone());
-                    line = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("reading template function"))??;
+            if let Some(attributes) = line.strip_prefix("% temp") {
+                const BLOCK: Option<&str> = Some("% temp");
+                let start = reader.line_number;
+                let attributes = Self::parse_attributes(attributes).map_err(|e| reader.located(start, None, BLOCK, e))?;
+                let template_line = reader.next_line(BLOCK, "reading template header")?;
+                let mut examples = Vec::new();
+                let mut exceptions = Vec::new();
+                while reader.next_starts_with("% example") || reader.next_starts_with("% nonexample") || reader.next_starts_with("% except") {
+                    let line = reader.next_line(BLOCK, "reading template examples")?;
+                    if let Some(exception) = line.strip_prefix("% except") {
+                        // The prefix is blanked out rather than removed so that parse errors point at the right column
+                        let padded = format!("{}{}", " ".repeat("% except".len()), exception);
+                        let exception = TemplateParser::parse_template(&padded)
+                            .map_err(|e| reader.located_parse_error(reader.line_number, BLOCK, e))?;
+                        exceptions.push(exception);
+                        continue;
+                    }
+                    let example = Self::parse_example(&line, reader.location(reader.line_number))
+                        .map_err(|e| reader.located(reader.line_number, None, BLOCK, e))?;
+                    examples.push(example);
                 }
-                let function_string = function_lines.into_iter().skip(1).collect::<Vec<_>>().join("\n");
-                let template = TemplateParser::parse_template(&template_line)?;
-                let function = CortexParser::parse_function(&function_string)?;
-                let processed_function = interpreter.preprocess_function(function)?;
+                let function_start = reader.line_number + 1;
+                let function_lines = reader.read_until_end(BLOCK, "reading template function")?;
+
+                let template = TemplateParser::parse_template(&template_line)
+                    .map_err(|e| reader.located_parse_error(start + 1, BLOCK, e))?;
+                let (function, param_types) = Self::load_function(reader, &function_lines, function_start, BLOCK, interpreter)?;
                 let entry = TemplateEntry {
+                    source: template_line,
                     template: template,
-                    function: processed_function,
+                    function: function,
+                    param_types: param_types,
+                    priority: attributes.priority,
+                    tags: attributes.tags,
+                    enabled: attributes.enabled,
+                    examples: examples,
+                    exceptions: exceptions,
                 };
                 self.templates.push(entry);
                 break;
             } else if line.starts_with("% sub") {
-                let name = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("reading subtemplate header"))??;
-                let mut subtemplate_lines = Vec::new();
-                line = String::new();
-                while !line.starts_with("% end") {
-                    subtemplate_lines.push(line.clone());
-                    line = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("reading subtemplate body"))??;
+                const BLOCK: Option<&str> = Some("% sub");
+                let start = reader.line_number;
+                let header = reader.next_line(BLOCK, "reading subtemplate header")?;
+                let (name, parameters) = Self::parse_subtemplate_name(&header).map_err(|e| reader.located(start + 1, None, BLOCK, e))?;
+                let subtemplate_lines = reader.read_until_end(BLOCK, "reading subtemplate body")?;
+                let subtemplate_str = subtemplate_lines.join("\n");
+                let subtemplate_template = TemplateParser::parse_template(&subtemplate_str)
+                    .map_err(|e| reader.located_parse_error(start + 2, BLOCK, e))?;
+                if self.matcher.add_parameterized_subtemplate(&name, parameters, subtemplate_template).is_some() {
+                    context.issues.push(ValidationIssue::DuplicateSubtemplate(name));
+                }
+                break;
+            } else if let Some(header) = line.strip_prefix("% entity") {
+                const BLOCK: Option<&str> = Some("% entity");
+                let start = reader.line_number;
+                let (name, memory_key) = Self::parse_entity_header(header).map_err(|e| reader.located(start, None, BLOCK, e))?;
+                let mut entity = Entity::new();
+                for value in reader.read_until_end(BLOCK, "reading entity values")? {
+                    if !value.trim().is_empty() {
+                        entity.add_line(&value);
+                    }
+                }
+                if let Some(key) = memory_key {
+                    let remembered = self.entity_resolver.as_ref().and_then(|resolve| resolve(&key));
+                    let memory_entity = MemoryEntity {
+                        name: name.clone(),
+                        key: key,
+                        declared: entity,
+                        remembered: remembered,
+                    };
+                    entity = memory_entity.entity();
+                    self.memory_entities.push(memory_entity);
+                }
+                if self.matcher.add_entity(&name, entity).is_some() {
+                    context.issues.push(ValidationIssue::DuplicateEntity(name));
                 }
-                let subtemplate_str = subtemplate_lines.join("");
-                let subtemplate_template = TemplateParser::parse_template(&subtemplate_str)?;
-                self.matcher.add_subtemplate(&name, subtemplate_template);
                 break;
             } else if line.starts_with("% fallback") {
-                let mut function_lines = Vec::new();
-                line = String::new();
-                while !line.starts_with("% end") {
-                    function_lines.push(line.clone());
-                    line = iter.next().ok_or(TemplateHandlerError::UnexpectedEof("reading fallback function"))??;
+                const BLOCK: Option<&str> = Some("% fallback");
+                let start = reader.line_number;
+                let function_lines = reader.read_until_end(BLOCK, "reading fallback function")?;
+                let (function, param_types) = Self::load_function(reader, &function_lines, start + 1, BLOCK, interpreter)?;
+                self.fallback = Some(function);
+                self.fallback_param_types = param_types;
+                break;
+            } else if let Some(include) = line.strip_prefix("% include") {
+                // Included paths are relative to the file that includes them
+                let path = reader.path.parent().unwrap_or(Path::new("")).join(include.trim());
+                let included = LineReader::open(&path, context).map_err(|e| reader.located(reader.line_number, None, None, e))?;
+                if let Some(included) = included {
+                    self.load_lines(included, interpreter, context)?;
                 }
-                let function_string = function_lines.into_iter().skip(1).collect::<Vec<_>>().join("\n");
-                let function = CortexParser::parse_function(&function_string)?;
-                let processed_function = interpreter.preprocess_function(function)?;
-                self.fallback = Some(processed_function);
                 break;
             } else {
-                return Err(Box::new(TemplateHandlerError::IllegalLine(line)));
+                return Err(Box::new(reader.located(reader.line_number, None, None, TemplateHandlerError::IllegalLine(line))));
             }
         }
         Ok(())
     }
+
+    // Parses and preprocesses the function whose first line is at `first_line` in the file
+    fn load_function(reader: &LineReader, lines: &[String], first_line: usize, block: Option<&'static str>, interpreter: &mut CortexInterpreter) -> Result<(RFunction, Vec<CortexType>), TemplateHandlerError> {
+        let function = CortexParser::parse_function(&lines.join("\n")).map_err(|e| {
+            // Cortex only reports the text of the line that failed, so find which line that was
+            let line = match &e {
+                CortexParseError::ParseFailure(_, text) => lines.iter().position(|l| l == text).map_or(first_line, |i| first_line + i),
+                _ => first_line,
+            };
+            reader.located(line, None, block, e)
+        })?;
+        let param_types = Self::param_types(&function);
+        let processed_function = interpreter.preprocess_function(function).map_err(|e| reader.located(first_line, None, block, e))?;
+        Ok((processed_function, param_types))
+    }
+
+    // Parses the `key=value` attributes following `% temp`: `priority=<number>`, `tags=<tag>,<tag>` and `enabled=<bool>`
+    fn parse_attributes(attributes: &str) -> Result<TemplateAttributes, TemplateHandlerError> {
+        let mut parsed = TemplateAttributes {
+            priority: 0,
+            tags: Vec::new(),
+            enabled: true,
+        };
+        for attribute in attributes.split_whitespace() {
+            let invalid = || TemplateHandlerError::InvalidAttribute(String::from(attribute));
+            match attribute.split_once('=') {
+                Some(("priority", value)) => parsed.priority = value.parse().map_err(|_e| invalid())?,
+                Some(("enabled", value)) => parsed.enabled = value.parse().map_err(|_e| invalid())?,
+                Some(("tags", value)) => {
+                    let tags: Vec<String> = value.split(',').map(String::from).collect();
+                    if tags.iter().any(|t| t.is_empty()) {
+                        return Err(invalid());
+                    }
+                    parsed.tags.extend(tags);
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    // Parses a subtemplate's name line, which is either `name` or `name(param, ...)`
+    fn parse_subtemplate_name(header: &str) -> Result<(String, Vec<String>), TemplateHandlerError> {
+        let invalid = || TemplateHandlerError::InvalidSubtemplateName(String::from(header));
+        let Some((name, parameters)) = header.split_once('(') else {
+            return Ok((String::from(header), Vec::new()));
+        };
+        let parameters: Vec<String> = parameters
+            .trim_end()
+            .strip_suffix(')')
+            .ok_or_else(invalid)?
+            .split(',')
+            .map(|p| String::from(p.trim()))
+            .collect();
+        if parameters.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_alphanumeric())) {
+            return Err(invalid());
+        }
+        Ok((String::from(name.trim()), parameters))
+    }
+
+    // Parses the `<name> [memory=<key>]` following `% entity`
+    fn parse_entity_header(header: &str) -> Result<(String, Option<String>), TemplateHandlerError> {
+        let invalid = || TemplateHandlerError::InvalidAttribute(String::from(header.trim()));
+        let mut parts = header.split_whitespace();
+        let name = parts.next().ok_or_else(invalid)?;
+        let memory_key = match parts.next().map(|a| a.split_once('=')) {
+            None => None,
+            Some(Some(("memory", key))) => Some(String::from(key)),
+            Some(_) => return Err(invalid()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok((String::from(name), memory_key))
+    }
+
+    // Parses `% example <utterance> => name=value, ...` (the bindings are optional) or `% nonexample <utterance>`
+    fn parse_example(line: &str, location: String) -> Result<TemplateExample, TemplateHandlerError> {
+        let invalid = || TemplateHandlerError::InvalidExample(String::from(line));
+        if let Some(utterance) = line.strip_prefix("% nonexample") {
+            return Ok(TemplateExample {
+                location: location,
+                utterance: String::from(utterance.trim()),
+                should_match: false,
+                bindings: Vec::new(),
+            });
+        }
+        let example = line.strip_prefix("% example").ok_or_else(invalid)?;
+        let (utterance, bindings) = example.split_once("=>").unwrap_or((example, ""));
+        let bindings = bindings
+            .split(',')
+            .filter(|b| !b.trim().is_empty())
+            .map(|b| {
+                let (name, value) = b.split_once('=').ok_or_else(invalid)?;
+                Ok((String::from(name.trim()), String::from(value.trim())))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if utterance.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(TemplateExample {
+            location: location,
+            utterance: String::from(utterance.trim()),
+            should_match: true,
+            bindings: bindings,
+        })
+    }
+
+    // Parameter types are lost when preprocessing, so they are kept so bindings can be converted to match them
+    fn param_types(function: &PFunction) -> Vec<CortexType> {
+        (0..function.num_params())
+            .map(|i| function.get_param(i).unwrap().param_type().clone())
+            .collect()
+    }
+}
+
+// State shared by every file read during a single load
+struct LoadContext {
+    issues: Vec<ValidationIssue>,
+    include_stack: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+}
+impl LoadContext {
+    fn new() -> Self {
+        LoadContext {
+            issues: Vec::new(),
+            include_stack: Vec::new(),
+            loaded: HashSet::new(),
+        }
+    }
+}
+
+// Reads the lines of a template file, keeping track of where in the file they came from
+struct LineReader {
+    path: PathBuf,
+    canonical_path: PathBuf,
+    lines: Peekable<Lines<BufReader<File>>>,
+    line_number: usize,
+}
+impl LineReader {
+    // Opens the file unless it has already been loaded. Fails if the file is already being loaded further up
+    // the chain of includes.
+    fn open(path: &Path, context: &LoadContext) -> Result<Option<Self>, Box<dyn Error>> {
+        let canonical_path = path.canonicalize().map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        if let Some(start) = context.include_stack.iter().position(|p| *p == canonical_path) {
+            let mut cycle: Vec<String> = context.include_stack[start..].iter().map(|p| p.display().to_string()).collect();
+            cycle.push(canonical_path.display().to_string());
+            return Err(Box::new(TemplateHandlerError::IncludeCycle(cycle)));
+        }
+        if context.loaded.contains(&canonical_path) {
+            return Ok(None);
+        }
+        let file = File::open(path)?;
+        Ok(Some(LineReader {
+            path: path.to_path_buf(),
+            canonical_path: canonical_path,
+            lines: BufReader::new(file).lines().peekable(),
+            line_number: 0,
+        }))
+    }
+
+    fn has_more(&mut self) -> bool {
+        self.lines.peek().is_some()
+    }
+    fn next_starts_with(&mut self, prefix: &str) -> bool {
+        matches!(self.lines.peek(), Some(Ok(line)) if line.starts_with(prefix))
+    }
+    fn next_line(&mut self, block: Option<&'static str>, context: &'static str) -> Result<String, TemplateHandlerError> {
+        match self.lines.next() {
+            Some(Ok(line)) => {
+                self.line_number += 1;
+                Ok(line)
+            },
+            Some(Err(e)) => Err(self.located(self.line_number + 1, None, block, e)),
+            None => Err(self.located(self.line_number, None, block, TemplateHandlerError::UnexpectedEof(context))),
+        }
+    }
+    // Reads lines up to (and consuming, but not including) the next `% end`
+    fn read_until_end(&mut self, block: Option<&'static str>, context: &'static str) -> Result<Vec<String>, TemplateHandlerError> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.next_line(block, context)?;
+            if line.starts_with("% end") {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+
+    fn location(&self, line: usize) -> String {
+        format!("{}:{}", self.path.display(), line)
+    }
+    fn located<E: Into<Box<dyn Error>>>(&self, line: usize, column: Option<usize>, block: Option<&'static str>, error: E) -> TemplateHandlerError {
+        TemplateHandlerError::Located {
+            path: self.path.display().to_string(),
+            line: line,
+            column: column,
+            block: block,
+            error: error.into(),
+        }
+    }
+    // Template parse errors know their position within the parsed text, which starts at `first_line` in the file
+    fn located_parse_error(&self, first_line: usize, block: Option<&'static str>, error: ParseError) -> TemplateHandlerError {
+        match error.location() {
+            Some((line, column)) => self.located(first_line + line - 1, Some(column), block, error),
+            None => self.located(first_line, None, block, error),
+        }
+    }
 }
 
 struct TemplateEntry {
+    source: String,
     template: Template,
     function: RFunction,
+    param_types: Vec<CortexType>,
+    priority: i32,
+    tags: Vec<String>,
+    enabled: bool,
+    examples: Vec<TemplateExample>,
+    // Templates from `% except` lines. An input that matches any of them never selects this template.
+    exceptions: Vec<Template>,
+}
+
+// An entity declared with `memory=<key>`, which is rebuilt whenever the values stored under the key change
+struct MemoryEntity {
+    name: String,
+    key: String,
+    // The values written in the `% entity` block
+    declared: Entity,
+    remembered: Option<Vec<String>>,
+}
+impl MemoryEntity {
+    fn entity(&self) -> Entity {
+        let mut entity = self.declared.clone();
+        for value in self.remembered.iter().flatten() {
+            entity.add_line(value);
+        }
+        entity
+    }
+}
+
+struct TemplateAttributes {
+    priority: i32,
+    tags: Vec<String>,
+    enabled: bool,
+}
+
+// An utterance from a `% example` or `% nonexample` line, along with the bindings it is expected to produce
+struct TemplateExample {
+    location: String,
+    utterance: String,
+    should_match: bool,
+    bindings: Vec<(String, String)>,
+}
+
+/// How well a template matched an input. Higher priorities win first, then fewer fuzzy corrections,
+/// then more literal words matched, then fewer characters captured by slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchScore {
+    pub priority: i32,
+    pub corrections: usize,
+    pub literal_words: usize,
+    pub wildcard_chars: usize,
+}
+impl Ord for MatchScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority)
+            .then(other.corrections.cmp(&self.corrections))
+            .then(self.literal_words.cmp(&other.literal_words))
+            .then(other.wildcard_chars.cmp(&self.wildcard_chars))
+    }
+}
+impl PartialOrd for MatchScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 pub struct MatchResult<'a> {
     pub function: &'a RFunction,
+    pub param_types: &'a Vec<CortexType>,
     pub match_inst: Match,
+    pub score: MatchScore,
+    /// Maps the normalized input that was matched back to the input given to `find_function`
+    pub input_map: TextMap,
+    template: &'a Template,
+    compiled: &'a CompiledTemplate,
+}
+impl MatchResult<'_> {
+    /// The byte range of a binding's text within the input given to `find_function`
+    pub fn input_span(&self, name: &str) -> Option<Range<usize>> {
+        self.input_map.original_range(self.match_inst.get_span(name)?)
+    }
+    /// The value bound to a slot, with text and list slots taken from `raw`, the transcript the input was sanitized from
+    pub fn raw_value(&self, name: &str, raw: &str, raw_map: &TextMap) -> Option<SlotValue> {
+        let value = self.match_inst.get_value(name)?;
+        let raw_span = self.input_span(name).and_then(|span| raw_map.original_range(span));
+        match (value, raw_span) {
+            (SlotValue::Text(_), Some(span)) if self.compiled.binds_text(name) => Some(SlotValue::Text(String::from(&raw[span]))),
+            (SlotValue::List(_), Some(span)) if self.compiled.binds_text(name) => Some(SlotValue::List(slots::parse_list(&raw[span]))),
+            _ => Some(value.clone()),
+        }
+    }
+}
+
+/// How a single template fared against an input, as reported by `TemplateHandler::explain`
+pub struct TemplateExplanation {
+    pub source: String,
+    pub regex: String,
+    /// Whether this is the template `find_function` would choose
+    pub selected: bool,
+    /// Whether the template can be chosen at all, given its `enabled` attribute and its tags
+    pub enabled: bool,
+    /// Whether the input matched one of the template's `% except` lines
+    pub excluded: bool,
+    /// The value bound to each slot, if the template matched
+    pub bindings: Vec<(String, String)>,
+    /// For templates that did not match, the longest start of the input that they accept
+    pub matched_prefix: Option<String>,
+}
+impl TemplateExplanation {
+    pub fn matched(&self) -> bool {
+        self.matched_prefix.is_none()
+    }
+}
+impl Display for TemplateExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match (self.matched(), self.selected, self.enabled, self.excluded) {
+            (true, true, _, _) => "match, selected",
+            (true, false, false, _) => "match, disabled",
+            (true, false, true, true) => "match, excepted",
+            (true, false, true, false) => "match",
+            (false, _, _, _) => "no match",
+        };
+        writeln!(f, "[{}] {}", status, self.source)?;
+        write!(f, "    regex: {}", self.regex)?;
+        for (name, value) in &self.bindings {
+            write!(f, "\n    {} = \"{}\"", name, value)?;
+        }
+        if let Some(prefix) = &self.matched_prefix {
+            if prefix.is_empty() {
+                write!(f, "\n    matched up to: (nothing)")?;
+            } else {
+                write!(f, "\n    matched up to: \"{}\"", prefix)?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,9 +1,9 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
-use regex::Regex;
+use regex::{Regex, RegexSet};
 use thiserror::Error;
 
-use super::template::{SymbolInternal, Template};
+use super::{entity::Entity, fuzzy::{self, Correction, FuzzyOptions}, normalizer::Normalizer, slots::{self, SlotValue}, template::{Clause, SlotType, Symbol, SymbolInternal, Template}, textmap::{self, TextMap}};
 
 #[derive(Error, Debug, PartialEq)]
 pub enum TemplateError {
@@ -11,97 +11,537 @@ pub enum TemplateError {
     SubtemplateNotFound(String),
     #[error("Template generated invalid regex")]
     InvalidRegex,
+    #[error("Subtemplate \"{0}\" refers to itself")]
+    RecursiveSubtemplate(String),
+    #[error("Entity \"{0}\" not found")]
+    EntityNotFound(String),
+    #[error("Subtemplate \"{0}\" takes {1} argument(s) but was given {2}")]
+    WrongArgumentCount(String, usize, usize),
+    #[error("Parameter \"${0}\" is not a parameter of the subtemplate it is used in")]
+    UnknownParameter(String),
 }
 
 pub struct TemplateMatcher {
     subtemplate_definitions: HashMap<String, Template>,
+    subtemplate_parameters: HashMap<String, Vec<String>>,
+    entity_definitions: HashMap<String, Entity>,
+    normalizer: Normalizer,
 }
 
 impl TemplateMatcher {
     pub fn new() -> Self {
         TemplateMatcher {
             subtemplate_definitions: HashMap::new(),
+            subtemplate_parameters: HashMap::new(),
+            entity_definitions: HashMap::new(),
+            normalizer: Normalizer::default(),
         }
     }
 
-    pub fn add_subtemplate(&mut self, name: &str, template: Template) {
-        self.subtemplate_definitions.insert(String::from(name), template);
+    /// Sets how template text is normalized. Input must be normalized the same way before it is matched
+    /// against compiled templates (`try_match` does this itself).
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.normalizer = normalizer;
+    }
+    pub fn get_normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    /// Adds a subtemplate definition, returning the definition it replaced (if any)
+    pub fn add_subtemplate(&mut self, name: &str, template: Template) -> Option<Template> {
+        self.add_parameterized_subtemplate(name, Vec::new(), template)
+    }
+    /// Adds a subtemplate that is called with arguments, as in `{toggle(light)}`. Its body refers to them as `$name`.
+    pub fn add_parameterized_subtemplate(&mut self, name: &str, parameters: Vec<String>, template: Template) -> Option<Template> {
+        self.subtemplate_parameters.insert(String::from(name), parameters);
+        self.subtemplate_definitions.insert(String::from(name), template)
+    }
+    pub fn get_subtemplate(&self, name: &str) -> Option<&Template> {
+        self.subtemplate_definitions.get(name)
+    }
+    pub fn subtemplate_names(&self) -> impl Iterator<Item = &String> {
+        self.subtemplate_definitions.keys()
+    }
+    pub fn get_subtemplate_parameters(&self, name: &str) -> &[String] {
+        self.subtemplate_parameters.get(name).map_or(&[], |p| p.as_slice())
+    }
+
+    /// Adds an entity definition, returning the definition it replaced (if any)
+    pub fn add_entity(&mut self, name: &str, entity: Entity) -> Option<Entity> {
+        self.entity_definitions.insert(String::from(name), entity)
+    }
+    pub fn get_entity(&self, name: &str) -> Option<&Entity> {
+        self.entity_definitions.get(name)
+    }
+
+    /// Returns the names of every slot in the template, including those inside subtemplates
+    pub fn slot_names(&self, template: &Template) -> Result<Vec<String>, TemplateError> {
+        let mut names = Vec::new();
+        self.visit_symbols(template, &mut |symbol| {
+            if let Some(label) = &symbol.label {
+                names.push(label.clone());
+            }
+            if let SymbolInternal::VarBind(slot) = &symbol.symbol {
+                names.push(slot.name.clone());
+            }
+        })?;
+        Ok(names)
     }
 
     pub fn try_match(&self, input: &str, template: &Template) -> Result<Option<Match>, TemplateError> {
+        let compiled = self.compile(template)?;
+        let (normalized, map) = self.normalizer.normalize_mapped(input);
+        Ok(compiled.try_match(&normalized).map(|mut mmatch| {
+            compiled.restore_original(&mut mmatch, input, &map);
+            mmatch
+        }))
+    }
+
+    pub fn compile(&self, template: &Template) -> Result<CompiledTemplate, TemplateError> {
         let regex_str = self.convert_template_to_regex(template)?;
-        let re = Regex::new(&regex_str).map_err(|_e| TemplateError::InvalidRegex)?;
-        if let Some(captures) = re.captures(input) {
-            let named_values: HashMap<String, String> = re
-                .capture_names()
-                .flatten()
-                .filter_map(|name| captures.name(name).map(|m| (name.to_string(), m.as_str().trim().to_string())))
-                .collect();
-
-            Ok(Some(Match { variable_bindings: named_values }))
-        } else {
-            Ok(None)
+        let regex = Regex::new(&regex_str).map_err(|_e| TemplateError::InvalidRegex)?;
+        let mut slot_types = HashMap::new();
+        let mut labels = Vec::new();
+        let mut entities = HashMap::new();
+        let mut vocabulary = Vec::new();
+        let mut add_word = |word: String| {
+            if !vocabulary.contains(&word) {
+                vocabulary.push(word);
+            }
+        };
+        self.visit_symbols(template, &mut |symbol| {
+            if let Some(label) = &symbol.label {
+                labels.push(label.clone());
+            }
+            match &symbol.symbol {
+                SymbolInternal::VarBind(slot) => {
+                    slot_types.insert(slot.name.clone(), slot.slot_type.clone());
+                    if let SlotType::Entity(name) = &slot.slot_type {
+                        if let Some(entity) = self.get_entity(name) {
+                            // Entity values are known words, so misheard values can be corrected too
+                            let entity = entity.normalized(&self.normalizer);
+                            entity.words().for_each(|w| add_word(String::from(w)));
+                            entities.insert(slot.name.clone(), entity);
+                        }
+                    }
+                },
+                SymbolInternal::Text(t) => {
+                    for word in self.normalizer.normalize(&t.to_lowercase()).split_whitespace() {
+                        add_word(String::from(word));
+                    }
+                },
+                _ => (),
+            }
+        })?;
+        Ok(CompiledTemplate {
+            regex: regex,
+            slot_types: slot_types,
+            labels: labels,
+            entities: entities,
+            vocabulary: vocabulary,
+        })
+    }
+    // Calls `f` on every symbol in the template, including those inside nested templates and subtemplates
+    fn visit_symbols(&self, template: &Template, f: &mut dyn FnMut(&Symbol)) -> Result<(), TemplateError> {
+        self.visit_symbols_internal(template, f, &mut Vec::new())
+    }
+    fn visit_symbols_internal(&self, template: &Template, f: &mut dyn FnMut(&Symbol), active: &mut Vec<String>) -> Result<(), TemplateError> {
+        for sym in template.all_symbols() {
+            f(sym);
+            if let SymbolInternal::SubtemplateCall(name) | SymbolInternal::ParameterizedCall(name, _) = &sym.symbol {
+                let subt = self.enter_subtemplate(name, active)?;
+                self.visit_symbols_internal(subt, f, active)?;
+                active.pop();
+            }
+        }
+        Ok(())
+    }
+    // Looks up a subtemplate and pushes it onto the stack of subtemplates being expanded, so that cycles are caught
+    // instead of recursing forever
+    pub(crate) fn enter_subtemplate(&self, name: &String, active: &mut Vec<String>) -> Result<&Template, TemplateError> {
+        if active.contains(name) {
+            return Err(TemplateError::RecursiveSubtemplate(name.clone()));
         }
+        let subt = self.subtemplate_definitions.get(name).ok_or_else(|| TemplateError::SubtemplateNotFound(name.clone()))?;
+        active.push(name.clone());
+        Ok(subt)
     }
 
     pub fn convert_template_to_regex(&self, template: &Template) -> Result<String, TemplateError> {
         let mut s = String::new();
         s.push_str("^");
-        s.push_str(&self.convert_template_to_regex_internal(template)?);
+        s.push_str(&self.convert_template_to_regex_internal(template, &mut Vec::new(), &HashMap::new())?);
         s.push_str("$");
         Ok(s)
     }
-    fn convert_template_to_regex_internal(&self, template: &Template) -> Result<String, TemplateError> {
+    // `arguments` holds the regex of each argument of the subtemplate being expanded, by parameter name
+    fn convert_template_to_regex_internal(&self, template: &Template, active: &mut Vec<String>, arguments: &HashMap<String, String>) -> Result<String, TemplateError> {
         let joint_clauses: Vec<String> = template.clauses.iter().map(|c| {
-            let joint_symbols: Vec<String> = c.symbols.iter().map(|sym| {
-                let mut parens_added = false;
-                let mut s = 
-                    match &sym.symbol {
-                        SymbolInternal::Text(t) => Ok(t.clone()),
-                        SymbolInternal::SubtemplateCall(t) => {
-                            parens_added = true;
-                            let subtemplate = self.subtemplate_definitions.get(t);
-                            if let Some(subt) = subtemplate {
-                                Ok(format!("(?:{})", self.convert_template_to_regex_internal(subt)?))
-                            } else {
-                                Err(TemplateError::SubtemplateNotFound(t.clone()))
-                            }
-                        },
-                        SymbolInternal::VarBind(name) => {
-                            parens_added = true;
-                            Ok(format!("(?<{}>.*)", name.clone()))
-                        },
-                        SymbolInternal::Template(template) => {
-                            let subtemplate_regex = self.convert_template_to_regex_internal(&template)?;
-                            parens_added = true;
-                            Ok(format!("(?:{})", subtemplate_regex))
-                        },
-                    }?;
-                if sym.optional {
-                    if parens_added {
-                        s.push_str("?");
-                    } else {
-                        s.insert_str(0, "(?:");
-                        s.push_str(")?");
-                    }
-                }
-                Ok(s)
-            }).collect::<Result<Vec<String>, TemplateError>>()?;
+            let joint_symbols = self.convert_clause_to_regexes(c, active, arguments)?;
             Ok(joint_symbols.join(" "))
         }).collect::<Result<Vec<String>, TemplateError>>()?;
-        let re = joint_clauses.join("|").replace(" ", r"\s*").to_lowercase();
+        let re = joint_clauses.join("|").replace(" ", r"\s*");
         Ok(re)
     }
+    // Converts each unit of the clause (see `clause_units`)
+    fn convert_clause_to_regexes(&self, clause: &Clause, active: &mut Vec<String>, arguments: &HashMap<String, String>) -> Result<Vec<String>, TemplateError> {
+        self.clause_units(clause).into_iter().map(|unit| self.convert_unit_to_regex(unit, active, arguments)).collect()
+    }
+    // Single symbols, or runs of required text when number words ("twenty five") are normalized
+    fn clause_units<'a>(&self, clause: &'a Clause) -> Vec<&'a [Symbol]> {
+        if !self.normalizer.number_words {
+            return clause.symbols.chunks(1).collect();
+        }
+        let required_text = |sym: &Symbol| matches!(sym.symbol, SymbolInternal::Text(_)) && !sym.optional;
+        clause.symbols.chunk_by(|a, b| required_text(a) && required_text(b)).collect()
+    }
+    fn convert_unit_to_regex(&self, unit: &[Symbol], active: &mut Vec<String>, arguments: &HashMap<String, String>) -> Result<String, TemplateError> {
+        match unit {
+            [sym] => self.convert_symbol_to_regex(sym, active, arguments),
+            words => {
+                let words: Vec<&str> = words
+                    .iter()
+                    .filter_map(|sym| match &sym.symbol {
+                        SymbolInternal::Text(t) => Some(t.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                Ok(self.text_to_regex(&words.join(" ")))
+            },
+        }
+    }
+    fn text_to_regex(&self, text: &str) -> String {
+        self.normalizer
+            .normalize(&text.to_lowercase())
+            .split_whitespace()
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(r"\s*")
+    }
+    fn convert_symbol_to_regex(&self, sym: &Symbol, active: &mut Vec<String>, arguments: &HashMap<String, String>) -> Result<String, TemplateError> {
+        let mut parens_added = false;
+        // A labeled group or subtemplate call captures the text it matched under the label
+        let group = match &sym.label {
+            Some(label) => format!("?<{}>", label),
+            None => String::from("?:"),
+        };
+        let mut s = 
+            match &sym.symbol {
+                SymbolInternal::Text(t) => Ok(self.text_to_regex(t)),
+                SymbolInternal::SubtemplateCall(t) => {
+                    parens_added = true;
+                    self.check_argument_count(t, 0)?;
+                    let subt = self.enter_subtemplate(t, active)?;
+                    let subtemplate_regex = self.convert_template_to_regex_internal(subt, active, &HashMap::new())?;
+                    active.pop();
+                    Ok(format!("({}{})", group, subtemplate_regex))
+                },
+                SymbolInternal::ParameterizedCall(t, args) => {
+                    parens_added = true;
+                    self.check_argument_count(t, args.len())?;
+                    // Arguments are expanded where they are written, so they may use the caller's own parameters
+                    let arg_regexes = args
+                        .iter()
+                        .map(|arg| self.convert_template_to_regex_internal(arg, active, arguments))
+                        .collect::<Result<Vec<String>, TemplateError>>()?;
+                    let subtemplate_arguments: HashMap<String, String> = self.get_subtemplate_parameters(t)
+                        .iter()
+                        .cloned()
+                        .zip(arg_regexes)
+                        .collect();
+                    let subt = self.enter_subtemplate(t, active)?;
+                    let subtemplate_regex = self.convert_template_to_regex_internal(subt, active, &subtemplate_arguments)?;
+                    active.pop();
+                    Ok(format!("({}{})", group, subtemplate_regex))
+                },
+                SymbolInternal::Parameter(name) => {
+                    parens_added = true;
+                    let argument = arguments.get(name).ok_or_else(|| TemplateError::UnknownParameter(name.clone()))?;
+                    Ok(format!("(?:{})", argument))
+                },
+                SymbolInternal::VarBind(slot) => {
+                    parens_added = true;
+                    let slot_regex = match &slot.slot_type {
+                        SlotType::Entity(name) => self.get_entity(name)
+                            .ok_or_else(|| TemplateError::EntityNotFound(name.clone()))?
+                            .normalized(&self.normalizer)
+                            .regex(),
+                        SlotType::Text | SlotType::List => slots::wildcard_regex(&slot.modifiers),
+                        other => slots::slot_regex(other),
+                    };
+                    Ok(format!("(?<{}>{})", slot.name, slot_regex))
+                },
+                SymbolInternal::Wildcard => {
+                    // Captured without a name, so that the filler counts as wildcard text when scoring but isn't bound
+                    parens_added = true;
+                    Ok(String::from("(.*?)"))
+                },
+                SymbolInternal::Template(template) => {
+                    let subtemplate_regex = self.convert_template_to_regex_internal(template, active, arguments)?;
+                    parens_added = true;
+                    Ok(format!("({}{})", group, subtemplate_regex))
+                },
+            }?;
+        if sym.optional {
+            if parens_added {
+                s.push_str("?");
+            } else {
+                s.insert_str(0, "(?:");
+                s.push_str(")?");
+            }
+        }
+        Ok(s)
+    }
+    fn check_argument_count(&self, name: &str, given: usize) -> Result<(), TemplateError> {
+        let expected = self.get_subtemplate_parameters(name).len();
+        if self.subtemplate_definitions.contains_key(name) && expected != given {
+            return Err(TemplateError::WrongArgumentCount(String::from(name), expected, given));
+        }
+        Ok(())
+    }
+
+    /// The length (in bytes) of the longest run of whole input words that the start of the template accepts
+    pub fn longest_prefix(&self, input: &str, template: &Template) -> Result<usize, TemplateError> {
+        let word_ends: Vec<usize> = textmap::word_ranges(input).iter().map(|w| w.end).collect();
+        let mut longest = 0;
+        for clause in &template.clauses {
+            let mut units = Vec::new();
+            for unit in self.clause_units(clause) {
+                units.push((self.convert_unit_to_regex(unit, &mut Vec::new(), &HashMap::new())?, Self::free_text(unit)));
+            }
+            // Each unit is only tried if every unit before it matched: "a(?:\s*b(?:\s*c)?)?"
+            let nested = units.iter().rev().fold(String::new(), |rest, (regex, free)| match (free, rest.is_empty()) {
+                (None, true) => regex.clone(),
+                (None, false) => format!(r"{}(?:\s*{})?", regex, rest),
+                (Some(_), true) => String::from(r"\S+"),
+                (Some(false), false) => format!(r"(?:\S+(?:\s+\S+)*\s*{}|\S+)", rest),
+                (Some(true), false) => format!(r"(?:(?:\S+(?:\s+\S+)*\s*)?{}|\S+)", rest),
+            });
+            let regex = Regex::new(&format!("^(?:{})$", nested)).map_err(|_e| TemplateError::InvalidRegex)?;
+            if let Some(end) = word_ends.iter().rev().find(|end| regex.is_match(&input[..**end])) {
+                longest = longest.max(*end);
+            }
+        }
+        Ok(longest)
+    }
+    // For a unit that could match any words (a text or list slot or a wildcard), whether it is optional
+    fn free_text(unit: &[Symbol]) -> Option<bool> {
+        match unit {
+            [sym] => match &sym.symbol {
+                SymbolInternal::VarBind(slot) if matches!(slot.slot_type, SlotType::Text | SlotType::List) => Some(sym.optional),
+                SymbolInternal::Wildcard => Some(sym.optional),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+pub struct CompiledTemplate {
+    regex: Regex,
+    slot_types: HashMap<String, SlotType>,
+    // Names bound by labeled groups, whose text is template text rather than a slot's
+    labels: Vec<String>,
+    entities: HashMap<String, Entity>,
+    vocabulary: Vec<String>,
+}
+impl CompiledTemplate {
+    /// Matches the input, converting each typed slot's text. Fails if a typed slot's text can't be converted.
+    pub fn try_match(&self, input: &str) -> Option<Match> {
+        self.match_with_spans(input).map(|(m, _)| m)
+    }
+
+    /// Matches the input exactly if possible, and otherwise after replacing words that are close to (or sound like)
+    /// words in the template. The corrections that were needed are reported on the match.
+    pub fn try_fuzzy_match(&self, input: &str, options: &FuzzyOptions) -> Option<Match> {
+        if let Some(m) = self.try_match(input) {
+            return Some(m);
+        }
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let corrections = fuzzy::find_corrections(&words, &self.vocabulary, options);
+        if corrections.is_empty() {
+            return None;
+        }
+        let (corrected, ranges, map) = fuzzy::apply_corrections(input, &corrections);
+        let (mut mmatch, spans) = self.match_with_spans(&corrected)?;
+
+        // A correction that landed inside a slot was probably part of the slot's value (e.g. a song title),
+        // so it is undone as long as the template still matches without it
+        let outside: Vec<Correction> = corrections
+            .iter()
+            .zip(ranges.iter())
+            .filter(|(_, r)| !spans.iter().any(|(s, e)| r.start < *e && r.end > *s))
+            .map(|(c, _)| c.clone())
+            .collect();
+        if outside.len() < corrections.len() {
+            let (corrected, _, map) = fuzzy::apply_corrections(input, &outside);
+            if let Some((m, _)) = self.match_with_spans(&corrected) {
+                mmatch = m;
+                mmatch.corrections = outside;
+                mmatch.map_spans(&map);
+                return Some(mmatch);
+            }
+        }
+        mmatch.corrections = corrections;
+        mmatch.map_spans(&map);
+        Some(mmatch)
+    }
+
+    /// Whether the value bound to the name is the text it captured (or the items of that text, for a list slot),
+    /// rather than an entity's value or a converted type
+    pub fn binds_text(&self, name: &str) -> bool {
+        !self.entities.contains_key(name) && matches!(self.slot_types.get(name), None | Some(SlotType::Text | SlotType::List))
+    }
+
+    /// Takes the text of text and list slots from `original`, the text the matched input was normalized from
+    pub fn restore_original(&self, mmatch: &mut Match, original: &str, map: &TextMap) {
+        for (name, span) in &mmatch.binding_spans {
+            let Some(text) = map.original_range(span.clone()).and_then(|range| original.get(range)) else {
+                continue;
+            };
+            mmatch.variable_bindings.insert(name.clone(), String::from(text));
+            if self.binds_text(name) {
+                let slot_type = self.slot_types.get(name).unwrap_or(&SlotType::Text);
+                mmatch.values.insert(name.clone(), slots::parse_slot(slot_type, text).unwrap());
+            }
+        }
+    }
+
+    fn match_with_spans(&self, input: &str) -> Option<(Match, Vec<(usize, usize)>)> {
+        let captures = self.regex.captures(input)?;
+        let named_values: HashMap<String, String> = self.regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| captures.name(name).map(|m| (name.to_string(), m.as_str().trim().to_string())))
+            .collect();
+        let binding_spans: HashMap<String, Range<usize>> = self.regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| captures.name(name).map(|m| {
+                let start = m.start() + (m.as_str().len() - m.as_str().trim_start().len());
+                (name.to_string(), start..start + m.as_str().trim().len())
+            }))
+            .collect();
+        let mut values = HashMap::new();
+        for (name, text) in &named_values {
+            let value = match self.entities.get(name) {
+                Some(entity) => SlotValue::Text(String::from(entity.resolve(text)?)),
+                None => slots::parse_slot(self.slot_types.get(name).unwrap_or(&SlotType::Text), text)?,
+            };
+            values.insert(name.clone(), value);
+        }
+
+        let spans: Vec<(usize, usize)> = self.regex
+            .capture_names()
+            .zip(captures.iter())
+            .skip(1)
+            .filter(|(name, _)| !name.is_some_and(|name| self.labels.iter().any(|l| l == name)))
+            .filter_map(|(_, m)| m)
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        let wildcard_chars = spans.iter().map(|(start, end)| input[*start..*end].trim().len()).sum();
+        let literal_words = word_starts(input)
+            .filter(|start| !spans.iter().any(|(s, e)| start >= s && start < e))
+            .count();
+
+        Some((
+            Match {
+                variable_bindings: named_values,
+                binding_spans: binding_spans,
+                values: values,
+                literal_words: literal_words,
+                wildcard_chars: wildcard_chars,
+                corrections: Vec::new(),
+            },
+            spans,
+        ))
+    }
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+}
+
+/// A group of compiled templates that can be screened against an input in a single pass
+pub struct CompiledTemplateSet {
+    set: RegexSet,
+    templates: Vec<CompiledTemplate>,
+}
+impl CompiledTemplateSet {
+    pub fn empty() -> Self {
+        CompiledTemplateSet {
+            set: RegexSet::empty(),
+            templates: Vec::new(),
+        }
+    }
+    pub fn new(templates: Vec<CompiledTemplate>) -> Result<Self, TemplateError> {
+        let set = RegexSet::new(templates.iter().map(|t| t.as_str())).map_err(|_e| TemplateError::InvalidRegex)?;
+        Ok(CompiledTemplateSet {
+            set: set,
+            templates: templates,
+        })
+    }
+
+    /// Returns the indices of every template that matches the input, in insertion order
+    pub fn candidates(&self, input: &str) -> Vec<usize> {
+        self.set.matches(input).into_iter().collect()
+    }
+    pub fn get(&self, index: usize) -> Option<&CompiledTemplate> {
+        self.templates.get(index)
+    }
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
 }
 
 pub struct Match {
     variable_bindings: HashMap<String, String>,
+    binding_spans: HashMap<String, Range<usize>>,
+    values: HashMap<String, SlotValue>,
+    literal_words: usize,
+    wildcard_chars: usize,
+    corrections: Vec<Correction>,
 }
 impl Match {
     pub fn get_binding(&self, name: &str) -> Option<&String> {
         self.variable_bindings.get(name)
     }
+    pub fn get_value(&self, name: &str) -> Option<&SlotValue> {
+        self.values.get(name)
+    }
     pub fn num_bindings(&self) -> usize {
         self.variable_bindings.len()
     }
+    /// The byte range of a binding's (trimmed) text within the matched input, as heard for fuzzy matches
+    pub fn get_span(&self, name: &str) -> Option<Range<usize>> {
+        self.binding_spans.get(name).cloned()
+    }
+    /// The number of input words that were matched by template text rather than captured by a slot
+    pub fn literal_words(&self) -> usize {
+        self.literal_words
+    }
+    /// The number of (non-whitespace-padded) characters captured by slots
+    pub fn wildcard_chars(&self) -> usize {
+        self.wildcard_chars
+    }
+    /// The words that had to be corrected for a fuzzy match to succeed (empty for exact matches)
+    pub fn corrections(&self) -> &[Correction] {
+        &self.corrections
+    }
+    // Moves the spans back to the text that was rewritten, dropping those that can't be traced back
+    fn map_spans(&mut self, map: &TextMap) {
+        self.binding_spans = self.binding_spans
+            .drain()
+            .filter_map(|(name, span)| map.original_range(span).map(|original| (name, original)))
+            .collect();
+    }
+}
+
+fn word_starts(input: &str) -> impl Iterator<Item = usize> + '_ {
+    input
+        .char_indices()
+        .filter(|(i, c)| !c.is_whitespace() && !input[..*i].ends_with(|p: char| !p.is_whitespace()))
+        .map(|(i, _)| i)
 }
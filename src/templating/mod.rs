@@ -2,3 +2,11 @@ pub mod template;
 pub mod parser;
 pub mod matcher;
 pub mod handler;
+pub mod slots;
+pub mod fuzzy;
+pub mod validation;
+pub mod generator;
+pub mod entity;
+pub mod normalizer;
+pub mod graph;
+pub mod textmap;
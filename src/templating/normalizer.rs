@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+use super::{slots, textmap::{self, TextMap}};
+
+const CONTRACTIONS: [(&str, &str); 6] = [
+    ("can't", "can not"), ("won't", "will not"), ("shan't", "shall not"),
+    ("let's", "let us"), ("ain't", "is not"), ("y'all", "you all"),
+];
+const SUFFIX_CONTRACTIONS: [(&str, &str); 5] = [("n't", " not"), ("'re", " are"), ("'ll", " will"), ("'ve", " have"), ("'m", " am")];
+// "'s" may also mean "has" or be possessive, so it is only expanded after words where it almost always means "is"
+const IS_CONTRACTABLE: [&str; 11] = ["what", "it", "that", "there", "here", "he", "she", "where", "who", "how", "when"];
+
+/// Rewrites text into a canonical form before matching. The same steps are applied to template text and to
+/// input, so that e.g. "what's" in a template matches "what is" in the input. No steps are enabled by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Normalizer {
+    /// Expand contractions such as "what's" and "don't" into "what is" and "do not"
+    pub contractions: bool,
+    /// Replace spoken numbers such as "twenty five" with digits
+    pub number_words: bool,
+    /// Replace accented letters with their plain forms, so "café" becomes "cafe"
+    pub accents: bool,
+}
+
+impl Normalizer {
+    /// Contractions and accents are normalized. Number words are left alone, since they are often part of
+    /// names and titles that slots capture.
+    pub fn standard() -> Self {
+        Normalizer {
+            contractions: true,
+            number_words: false,
+            accents: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.contractions || self.number_words || self.accents
+    }
+
+    /// Applies the enabled steps. Text is returned unchanged if no steps are enabled; otherwise its words
+    /// are rejoined with single spaces.
+    pub fn normalize(&self, text: &str) -> String {
+        self.normalize_mapped(text).0
+    }
+
+    /// Normalizes the text, also returning a map from the normalized text back to the given text
+    pub fn normalize_mapped(&self, text: &str) -> (String, TextMap) {
+        if !self.is_enabled() {
+            return (String::from(text), TextMap::identity(text));
+        }
+        // Each word keeps the range of the original text it came from
+        let mut words: Vec<(String, Range<usize>)> = textmap::word_ranges(text)
+            .into_iter()
+            .map(|r| (String::from(&text[r.clone()]), r))
+            .collect();
+        if self.accents {
+            words = words.into_iter().map(|(w, r)| (fold_accents(&w), r)).collect();
+        }
+        if self.contractions {
+            words = words
+                .into_iter()
+                .flat_map(|(w, r)| expand_contraction(&w).split(' ').map(|e| (String::from(e), r.clone())).collect::<Vec<_>>())
+                .collect();
+        }
+        if self.number_words {
+            words = numbers_to_digits(&words);
+        }
+
+        let mut normalized = String::new();
+        let mut map = TextMap::new();
+        for (word, range) in words {
+            if !normalized.is_empty() {
+                normalized.push(' ');
+            }
+            let start = normalized.len();
+            normalized.push_str(&word);
+            if word == text[range.clone()] {
+                map.push_copied(start..normalized.len(), range.start);
+            } else {
+                map.push(start..normalized.len(), range);
+            }
+        }
+        (normalized, map)
+    }
+}
+
+/// Lowercases a transcript and strips everything but letters, digits, whitespace and apostrophes (so that
+/// contractions such as "what's" can be matched). Also returns a map from the sanitized text back to the transcript.
+pub fn sanitize(input: &str) -> (String, TextMap) {
+    let mut sanitized = String::new();
+    let mut map = TextMap::new();
+    for (i, c) in input.char_indices() {
+        let start = sanitized.len();
+        for lower in c.to_lowercase() {
+            let lower = if lower == '’' { '\'' } else { lower };
+            if lower.is_alphanumeric() || lower.is_whitespace() || lower == '\'' {
+                sanitized.push(lower);
+            }
+        }
+        if sanitized[start..] == input[i..i + c.len_utf8()] {
+            map.push_copied(start..sanitized.len(), i);
+        } else if sanitized.len() > start {
+            map.push(start..sanitized.len(), i..i + c.len_utf8());
+        }
+    }
+    (sanitized, map)
+}
+
+fn fold_accents(word: &str) -> String {
+    let mut folded = String::new();
+    for c in word.chars() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let plain = match lower {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+            'ç' | 'ć' | 'č' => "c",
+            'ď' | 'đ' => "d",
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' => "i",
+            'ñ' | 'ń' | 'ň' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => "o",
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => "u",
+            'ý' | 'ÿ' => "y",
+            'ś' | 'š' => "s",
+            'ź' | 'ż' | 'ž' => "z",
+            'ł' => "l",
+            'ř' => "r",
+            'ť' => "t",
+            'ß' => "ss",
+            'æ' => "ae",
+            'œ' => "oe",
+            _ => {
+                folded.push(c);
+                continue;
+            },
+        };
+        if c.is_uppercase() {
+            folded.push_str(&plain.to_uppercase());
+        } else {
+            folded.push_str(plain);
+        }
+    }
+    folded
+}
+
+fn expand_contraction(word: &str) -> String {
+    let lower = word.to_lowercase().replace('’', "'");
+    if let Some((_, expanded)) = CONTRACTIONS.iter().find(|(c, _)| *c == lower) {
+        return String::from(*expanded);
+    }
+    for (suffix, expanded) in SUFFIX_CONTRACTIONS {
+        if let Some(stem) = lower.strip_suffix(suffix) {
+            if !stem.is_empty() {
+                return format!("{}{}", stem, expanded);
+            }
+        }
+    }
+    if let Some(stem) = lower.strip_suffix("'s") {
+        if IS_CONTRACTABLE.contains(&stem) {
+            return format!("{} is", stem);
+        }
+    }
+    String::from(word)
+}
+
+// Replaces each run of number words with its value. "and" is only part of a number after "hundred" or a scale,
+// as in "one hundred and five", and runs that lead into an ordinal ("twenty first") are left alone.
+fn numbers_to_digits(words: &[(String, Range<usize>)]) -> Vec<(String, Range<usize>)> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let mut end = i;
+        while end < words.len() {
+            let word = words[end].0.to_lowercase();
+            let joins = word == "and"
+                && end > i
+                && matches!(words[end - 1].0.to_lowercase().as_str(), "hundred" | "thousand" | "million" | "billion")
+                && words.get(end + 1).is_some_and(|(w, _)| slots::is_number_word(&w.to_lowercase()));
+            if slots::is_number_word(&word) || joins {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        let leads_into_ordinal = words.get(end).is_some_and(|(w, _)| slots::is_ordinal_word(&w.to_lowercase()));
+        let run: Vec<&str> = words[i..end].iter().map(|(w, _)| w.as_str()).collect();
+        let value = (end > i && !leads_into_ordinal)
+            .then(|| slots::parse_number(&run.join(" ").to_lowercase()))
+            .flatten();
+        match value {
+            Some(n) => {
+                output.push((n.to_string(), words[i].1.start..words[end - 1].1.end));
+                i = end;
+            },
+            None => {
+                output.push(words[i].clone());
+                i += 1;
+            },
+        }
+    }
+    output
+}
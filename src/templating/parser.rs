@@ -1,8 +1,8 @@
-use pest::{iterators::Pair, Parser};
+use pest::{error::LineColLocation, iterators::Pair, Parser};
 use pest_derive::Parser;
 use thiserror::Error;
 
-use super::template::{Clause, Symbol, SymbolInternal, Template};
+use super::template::{Clause, Slot, SlotModifiers, SlotType, Symbol, SymbolInternal, Template};
 
 #[derive(Parser)]
 #[grammar = "templating/grammar.pest"] // relative to src
@@ -12,10 +12,27 @@ pub struct TemplateParser;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
-    #[error("Failed to parse template: {0}")]
-    FailTemplate(String),
+    #[error("Failed to parse template:\n{0}")]
+    FailTemplate(Box<pest::error::Error<Rule>>),
     #[error("Failed to parse symbol: {0}")]
     FailSymbol(String),
+    #[error("Unknown slot type: {0}")]
+    FailSlotType(String),
+    #[error("Invalid slot modifier: {0}")]
+    FailSlotModifier(String),
+}
+
+impl ParseError {
+    /// The line and column (both starting at 1) within the parsed input where parsing failed, if known
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match self {
+            ParseError::FailTemplate(e) => match e.line_col {
+                LineColLocation::Pos(pos) => Some(pos),
+                LineColLocation::Span(start, _) => Some(start),
+            },
+            _ => None,
+        }
+    }
 }
 
 impl TemplateParser {
@@ -23,7 +40,7 @@ impl TemplateParser {
         let pair = PestTemplateParser::parse(Rule::topTemplate, input);
         match pair {
             Ok(mut v) => Self::parse_template_pair(v.next().unwrap().into_inner().next().unwrap()),
-            Err(_) => Err(ParseError::FailTemplate(String::from(input))),
+            Err(e) => Err(ParseError::FailTemplate(Box::new(e))),
         }
     }
 
@@ -49,13 +66,18 @@ impl TemplateParser {
         for sym in symbols.into_iter() {
             let symbol_split = Self::split_words(sym.symbol);
             let optional = sym.optional;
+            let mut label = sym.label;
             let len = symbol_split.len();
             let symbols_to_add: Vec<_> = symbol_split
                 .into_iter()
                 .enumerate()
                 .map(|(i, internal)| {
                     if i == len - 1 {
-                        Symbol::new(internal, optional)
+                        Symbol {
+                            symbol: internal,
+                            optional: optional,
+                            label: label.take(),
+                        }
                     } else {
                         Symbol::new(internal, false)
                     }
@@ -74,11 +96,15 @@ impl TemplateParser {
     fn parse_symbol(pair: Pair<Rule>) -> Result<Symbol, ParseError> {
         let pair_str = pair.as_str();
         let symbol_internal;
-        let internal_pair = pair.into_inner().next().unwrap();
+        let mut pairs = pair.into_inner();
+        let internal_pair = pairs.next().unwrap();
+        let label = pairs.next().map(|p| String::from(p.into_inner().next().unwrap().as_str()));
         match internal_pair.as_rule() {
             Rule::text => symbol_internal = SymbolInternal::Text(String::from(internal_pair.as_str())),
-            Rule::varBind => symbol_internal = SymbolInternal::VarBind(String::from(internal_pair.into_inner().next().unwrap().as_str())),
-            Rule::subtemplateCall => symbol_internal = SymbolInternal::SubtemplateCall(String::from(internal_pair.into_inner().next().unwrap().as_str())),
+            Rule::varBind => symbol_internal = SymbolInternal::VarBind(Self::parse_slot(internal_pair)?),
+            Rule::subtemplateCall => symbol_internal = Self::parse_subtemplate_call(internal_pair)?,
+            Rule::parameter => symbol_internal = SymbolInternal::Parameter(String::from(internal_pair.into_inner().next().unwrap().as_str())),
+            Rule::wildcard => symbol_internal = SymbolInternal::Wildcard,
             Rule::template => symbol_internal = SymbolInternal::Template(Box::new(Self::parse_template_pair(internal_pair)?)),
             _ => return Err(ParseError::FailSymbol(String::from(pair_str))),
         }
@@ -86,9 +112,71 @@ impl TemplateParser {
         Ok(Symbol {
             symbol: symbol_internal,
             optional: optional,
+            label: label,
         })
     }
 
+    fn parse_subtemplate_call(pair: Pair<Rule>) -> Result<SymbolInternal, ParseError> {
+        let mut pairs = pair.into_inner();
+        let name = String::from(pairs.next().unwrap().as_str().trim());
+        match pairs.next() {
+            Some(arguments) => {
+                let args = arguments
+                    .into_inner()
+                    .map(Self::parse_template_pair)
+                    .collect::<Result<Vec<Template>, ParseError>>()?;
+                Ok(SymbolInternal::ParameterizedCall(name, args))
+            },
+            None => Ok(SymbolInternal::SubtemplateCall(name)),
+        }
+    }
+
+    fn parse_slot(pair: Pair<Rule>) -> Result<Slot, ParseError> {
+        let mut pairs = pair.into_inner().peekable();
+        let name = pairs.next().unwrap().as_str();
+        let slot_type = match pairs.next_if(|p| p.as_rule() != Rule::slotModifier) {
+            None => SlotType::Text,
+            Some(p) if p.as_rule() == Rule::entityRef => SlotType::Entity(String::from(p.into_inner().next().unwrap().as_str())),
+            Some(p) => match p.as_str() {
+                "..." => SlotType::List,
+                "number" => SlotType::Number,
+                "duration" => SlotType::Duration,
+                "ordinal" => SlotType::Ordinal,
+                "bool" => SlotType::Bool,
+                other => return Err(ParseError::FailSlotType(String::from(other))),
+            },
+        };
+
+        let mut modifiers = SlotModifiers::default();
+        for modifier in pairs {
+            let text = modifier.as_str();
+            // Only free text can be cut down, since the other types already decide what they capture
+            if !matches!(slot_type, SlotType::Text | SlotType::List) {
+                return Err(ParseError::FailSlotModifier(format!("{} (on {})", text, name)));
+            }
+            match text {
+                "lazy" => modifiers.lazy = true,
+                "required" => modifiers.required = true,
+                bounds => modifiers.words = Some(Self::parse_word_bounds(bounds)?),
+            }
+        }
+        Ok(Slot::typed(name, slot_type).with_modifiers(modifiers))
+    }
+
+    fn parse_word_bounds(bounds: &str) -> Result<(usize, Option<usize>), ParseError> {
+        let invalid = || ParseError::FailSlotModifier(String::from(bounds));
+        let (min, max) = bounds.split_once("..").ok_or_else(invalid)?;
+        let min = min.parse::<usize>().map_err(|_| invalid())?;
+        let max = match max {
+            "" => None,
+            max => Some(max.parse::<usize>().map_err(|_| invalid())?),
+        };
+        if max.is_some_and(|max| max < min || max == 0) {
+            return Err(invalid());
+        }
+        Ok((min, max))
+    }
+
     fn split_words(symbol: SymbolInternal) -> Vec<SymbolInternal> {
         if let SymbolInternal::Text(text) = symbol {
             let words_str: Vec<&str> = text.split_whitespace().collect();
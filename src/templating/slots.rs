@@ -0,0 +1,277 @@
+use std::fmt::Display;
+
+use super::template::{SlotModifiers, SlotType};
+
+const UNITS: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const SCALES: [(&str, f64); 3] = [("thousand", 1e3), ("million", 1e6), ("billion", 1e9)];
+
+const ORDINAL_UNITS: [&str; 20] = [
+    "zeroth", "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth", "tenth",
+    "eleventh", "twelfth", "thirteenth", "fourteenth", "fifteenth", "sixteenth", "seventeenth", "eighteenth", "nineteenth",
+];
+const ORDINAL_TENS: [&str; 10] = ["", "", "twentieth", "thirtieth", "fortieth", "fiftieth", "sixtieth", "seventieth", "eightieth", "ninetieth"];
+
+const DURATION_UNITS: [(&str, f64); 14] = [
+    ("second", 1.0), ("seconds", 1.0), ("sec", 1.0), ("secs", 1.0),
+    ("minute", 60.0), ("minutes", 60.0), ("min", 60.0), ("mins", 60.0),
+    ("hour", 3600.0), ("hours", 3600.0),
+    ("day", 86400.0), ("days", 86400.0),
+    ("week", 604800.0), ("weeks", 604800.0),
+];
+
+const TRUE_WORDS: [&str; 9] = ["yes", "yeah", "yep", "yup", "sure", "true", "okay", "ok", "on"];
+const FALSE_WORDS: [&str; 5] = ["no", "nope", "nah", "false", "off"];
+
+/// The value bound to a slot after it has been converted according to its `SlotType`.
+/// Durations are converted to a number of seconds and ordinals to their position.
+#[derive(PartialEq, Debug, Clone)]
+pub enum SlotValue {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    List(Vec<String>),
+}
+impl Display for SlotValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SlotValue::Text(t) => write!(f, "{}", t),
+            SlotValue::Number(n) => write!(f, "{}", n),
+            SlotValue::Boolean(b) => write!(f, "{}", b),
+            SlotValue::List(items) => write!(f, "{}", items.join(", ")),
+        }
+    }
+}
+
+/// Returns the regex (without a capture group) that text bound to a slot of this type must match
+pub fn slot_regex(slot_type: &SlotType) -> String {
+    let number_word = number_word_regex();
+    match slot_type {
+        // Entity values are only known to the matcher, which supplies their regex itself
+        SlotType::Text | SlotType::List | SlotType::Entity(_) => String::from(".*"),
+        SlotType::Number => number_regex(),
+        SlotType::Ordinal => {
+            let ordinal_word = alternation(ORDINAL_UNITS.iter().chain(ORDINAL_TENS.iter()).chain(["hundredth", "thousandth"].iter()).copied());
+            format!(r"(?:\d+(?:st|nd|rd|th)|(?:{}(?:\s+|-))*{})", number_word, ordinal_word)
+        },
+        SlotType::Duration => {
+            let unit = alternation(DURATION_UNITS.iter().map(|(u, _)| *u));
+            let part = format!(r"(?:{}|an?|half\s+an?)\s+{}(?:\s+and\s+a\s+half)?", number_regex(), unit);
+            format!(r"(?:{}(?:(?:\s+and\s+|,\s*|\s+){})*)", part, part)
+        },
+        SlotType::Bool => alternation(TRUE_WORDS.iter().chain(FALSE_WORDS.iter()).copied()),
+    }
+}
+
+/// Returns the regex (without a capture group) for a text or list slot with the given modifiers
+pub fn wildcard_regex(modifiers: &SlotModifiers) -> String {
+    let lazy = if modifiers.lazy { "?" } else { "" };
+    match modifiers.words {
+        None if modifiers.required => format!(r"\S.*{}", lazy),
+        None => format!(".*{}", lazy),
+        Some((min, max)) => {
+            let min = if modifiers.required { min.max(1) } else { min };
+            let max = max.map_or_else(String::new, |max| (max - 1).to_string());
+            if min == 0 {
+                format!(r"(?:\S+(?:\s+\S+){{0,{}}}{})?{}", max, lazy, lazy)
+            } else {
+                format!(r"\S+(?:\s+\S+){{{},{}}}{}", min - 1, max, lazy)
+            }
+        },
+    }
+}
+
+/// Converts the text captured by a slot, or returns `None` if it is not valid for the slot's type
+pub fn parse_slot(slot_type: &SlotType, text: &str) -> Option<SlotValue> {
+    match slot_type {
+        SlotType::Text | SlotType::Entity(_) => Some(SlotValue::Text(String::from(text))),
+        SlotType::Number => parse_number(text).map(SlotValue::Number),
+        SlotType::Ordinal => parse_ordinal(text).map(SlotValue::Number),
+        SlotType::Duration => parse_duration(text).map(SlotValue::Number),
+        SlotType::Bool => parse_bool(text).map(SlotValue::Boolean),
+        SlotType::List => Some(SlotValue::List(parse_list(text))),
+    }
+}
+
+/// Parses digits ("25", "2.5") or spoken numbers ("twenty five", "one hundred and three", "two point five")
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(n) = text.parse::<f64>() {
+        return Some(n);
+    }
+    let words = tokenize(text);
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut total = 0.0;
+    let mut current = 0.0;
+    let mut iter = words.iter();
+    while let Some(word) = iter.next() {
+        let word = *word;
+        if word == "and" {
+            continue;
+        } else if word == "point" {
+            let mut decimals = String::new();
+            for digit in iter.by_ref() {
+                decimals.push_str(&UNITS[..10].iter().position(|u| *u == *digit)?.to_string());
+            }
+            if decimals.is_empty() {
+                return None;
+            }
+            current += format!("0.{}", decimals).parse::<f64>().ok()?;
+        } else if let Some(n) = UNITS.iter().position(|u| *u == word) {
+            current += n as f64;
+        } else if let Some(n) = TENS.iter().position(|t| !t.is_empty() && *t == word) {
+            current += (n * 10) as f64;
+        } else if word == "hundred" {
+            current = if current == 0.0 { 100.0 } else { current * 100.0 };
+        } else if let Some((_, scale)) = SCALES.iter().find(|(s, _)| *s == word) {
+            total += if current == 0.0 { *scale } else { current * scale };
+            current = 0.0;
+        } else {
+            return None;
+        }
+    }
+    Some(total + current)
+}
+
+/// Parses "3rd" or "twenty first" style ordinals into the position they name
+pub fn parse_ordinal(text: &str) -> Option<f64> {
+    let text = text.trim();
+    for suffix in ["st", "nd", "rd", "th"] {
+        if let Some(digits) = text.strip_suffix(suffix) {
+            if let Ok(n) = digits.parse::<u64>() {
+                return Some(n as f64);
+            }
+        }
+    }
+
+    let words = tokenize(text);
+    let (last, rest) = words.split_last()?;
+    let prefix = if rest.is_empty() { None } else { Some(parse_number(&rest.join(" "))?) };
+    if let Some(n) = ORDINAL_UNITS.iter().position(|o| o == last) {
+        Some(prefix.unwrap_or(0.0) + n as f64)
+    } else if let Some(n) = ORDINAL_TENS.iter().position(|o| !o.is_empty() && o == last) {
+        Some(prefix.unwrap_or(0.0) + (n * 10) as f64)
+    } else if *last == "hundredth" {
+        Some(prefix.unwrap_or(1.0) * 100.0)
+    } else if *last == "thousandth" {
+        Some(prefix.unwrap_or(1.0) * 1000.0)
+    } else {
+        None
+    }
+}
+
+/// Parses durations such as "five minutes", "an hour and a half" or "1 hour 30 minutes" into seconds
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let words = tokenize(text);
+    let mut total = 0.0;
+    let mut amount_words: Vec<&str> = Vec::new();
+    let mut last_unit = None;
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        if let Some((_, seconds)) = DURATION_UNITS.iter().find(|(u, _)| *u == word) {
+            total += parse_duration_amount(&amount_words)? * seconds;
+            amount_words.clear();
+            last_unit = Some(*seconds);
+        } else if words[i..].starts_with(&["and", "a", "half"]) && amount_words.is_empty() {
+            total += 0.5 * last_unit?;
+            i += 2;
+        } else if word == "and" && amount_words.is_empty() {
+            // Joins two parts, as in "one hour and five minutes"
+        } else {
+            amount_words.push(word);
+        }
+        i += 1;
+    }
+    if !amount_words.is_empty() || last_unit.is_none() {
+        return None;
+    }
+    Some(total)
+}
+
+fn parse_duration_amount(words: &[&str]) -> Option<f64> {
+    match words {
+        ["a"] | ["an"] => Some(1.0),
+        ["half", "a"] | ["half", "an"] => Some(0.5),
+        _ => parse_number(&words.join(" ")),
+    }
+}
+
+pub fn parse_bool(text: &str) -> Option<bool> {
+    let text = text.trim();
+    if TRUE_WORDS.contains(&text) {
+        Some(true)
+    } else if FALSE_WORDS.contains(&text) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Splits "milk, eggs and bread" into its items. Items may be separated by commas, "and" or "or" (in any case).
+pub fn parse_list(text: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for word in text.split_inclusive(',').flat_map(|part| part.split_whitespace()) {
+        let (word, ends_item) = match word.strip_suffix(',') {
+            Some(word) => (word, true),
+            None => (word, false),
+        };
+        if word.eq_ignore_ascii_case("and") || word.eq_ignore_ascii_case("or") {
+            items.push(current.join(" "));
+            current.clear();
+        } else if !word.is_empty() {
+            current.push(word);
+        }
+        if ends_item {
+            items.push(current.join(" "));
+            current.clear();
+        }
+    }
+    items.push(current.join(" "));
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Whether the word can be part of a spoken number, as in "two hundred and five"
+pub fn is_number_word(word: &str) -> bool {
+    UNITS.contains(&word)
+        || TENS.iter().any(|t| !t.is_empty() && *t == word)
+        || word == "hundred"
+        || SCALES.iter().any(|(s, _)| *s == word)
+}
+pub fn is_ordinal_word(word: &str) -> bool {
+    ORDINAL_UNITS.contains(&word) || ORDINAL_TENS.iter().any(|o| !o.is_empty() && *o == word) || word == "hundredth" || word == "thousandth"
+}
+
+fn tokenize(text: &str) -> Vec<&str> {
+    text.split(|c: char| c.is_whitespace() || c == '-' || c == ',')
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn number_word_regex() -> String {
+    let words = UNITS.iter()
+        .chain(TENS.iter().filter(|t| !t.is_empty()))
+        .chain(["hundred"].iter())
+        .chain(SCALES.iter().map(|(s, _)| s));
+    alternation(words.copied())
+}
+
+fn number_regex() -> String {
+    let word = number_word_regex();
+    format!(r"(?:-?\d+(?:\.\d+)?|{}(?:(?:\s+|-)(?:and\s+)?{})*(?:\s+point(?:\s+{})+)?)", word, word, word)
+}
+
+// Longer words come first so that e.g. "seventeen" is not matched as "seven"
+fn alternation<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut words: Vec<&str> = words.collect();
+    words.sort_by_key(|w| std::cmp::Reverse(w.len()));
+    format!(r"(?:{})\b", words.join("|"))
+}
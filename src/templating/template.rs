@@ -1,26 +1,93 @@
-#[derive(PartialEq, Debug)]
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub enum SymbolInternal {
     Text(String),
     SubtemplateCall(String),
-    VarBind(String),
+    VarBind(Slot),
     Template(Box<Template>),
+    /// A call to a subtemplate that takes arguments, as in `{toggle(light)}`
+    ParameterizedCall(String, Vec<Template>),
+    /// A reference to one of the enclosing subtemplate's parameters, as in `$device`
+    Parameter(String),
+    /// Filler words that are matched but not bound, written `*`. As few words as possible are matched.
+    Wildcard,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum SlotType {
+    Text,
+    Number,
+    Duration,
+    Ordinal,
+    Bool,
+    /// Several items separated by commas, "and" or "or", written as `[items...]`
+    List,
+    /// One of the values of the named entity, written as `[room:{rooms}]`. The canonical value is bound.
+    Entity(String),
+}
+
+/// Restrictions on the text a text or list slot captures, written after its type as in `[task:lazy:1..8]`
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotModifiers {
+    /// Capture as few words as possible rather than as many as possible (`:lazy`)
+    pub lazy: bool,
+    /// Never capture an empty value (`:required`)
+    pub required: bool,
+    /// The least and (if given) most words the slot captures (`:1..8` or `:2..`)
+    pub words: Option<(usize, Option<usize>)>,
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct Slot {
+    pub(crate) name: String,
+    pub(crate) slot_type: SlotType,
+    pub(crate) modifiers: SlotModifiers,
+}
+impl Slot {
+    pub fn new(name: &str) -> Self {
+        Self::typed(name, SlotType::Text)
+    }
+    pub fn typed(name: &str, slot_type: SlotType) -> Self {
+        Self {
+            name: String::from(name),
+            slot_type: slot_type,
+            modifiers: SlotModifiers::default(),
+        }
+    }
+    pub fn with_modifiers(mut self, modifiers: SlotModifiers) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Symbol {
     pub(crate) symbol: SymbolInternal,
     pub(crate) optional: bool,
+    /// The name that the text matched by a group or subtemplate call is bound to, as in `(on|off)=state`
+    pub(crate) label: Option<String>,
 }
 impl Symbol {
     pub fn new(symbol: SymbolInternal, optional: bool) -> Self {
         Self {
             symbol: symbol,
             optional: optional,
+            label: None,
+        }
+    }
+    pub fn labeled(symbol: SymbolInternal, optional: bool, label: &str) -> Self {
+        Self {
+            symbol: symbol,
+            optional: optional,
+            label: Some(String::from(label)),
         }
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Clause {
     pub(crate) symbols: Vec<Symbol>,
 }
@@ -37,7 +104,7 @@ impl Clause {
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Template {
     pub(crate) clauses: Vec<Clause>,
 }
@@ -52,4 +119,107 @@ impl Template {
             clauses: vec![c],
         }
     }
+
+    /// Every symbol in the template in the order they are written, including those inside groups and
+    /// subtemplate arguments (but not those inside the subtemplates it calls)
+    pub fn all_symbols(&self) -> Vec<&Symbol> {
+        let mut symbols = Vec::new();
+        self.collect_symbols(&mut symbols);
+        symbols
+    }
+    fn collect_symbols<'a>(&'a self, symbols: &mut Vec<&'a Symbol>) {
+        for symbol in self.clauses.iter().flat_map(|c| c.symbols.iter()) {
+            symbols.push(symbol);
+            match &symbol.symbol {
+                SymbolInternal::Template(inner) => inner.collect_symbols(symbols),
+                SymbolInternal::ParameterizedCall(_, args) => args.iter().for_each(|arg| arg.collect_symbols(symbols)),
+                SymbolInternal::Text(_) | SymbolInternal::SubtemplateCall(_) | SymbolInternal::VarBind(_) | SymbolInternal::Parameter(_) | SymbolInternal::Wildcard => (),
+            }
+        }
+    }
+}
+
+// The Display implementations write templates in the syntax the parser reads, so that parsing the output gives back
+// an equal template
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, clause) in self.clauses.iter().enumerate() {
+            if i > 0 {
+                write!(f, "|")?;
+            }
+            write!(f, "{}", clause)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Clause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if let Some(label) = &self.label {
+            write!(f, "={}", label)?;
+        }
+        if self.optional {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SymbolInternal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolInternal::Text(t) => write!(f, "{}", t),
+            SymbolInternal::SubtemplateCall(name) => write!(f, "{{{}}}", name),
+            SymbolInternal::ParameterizedCall(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{{{}({})}}", name, args.join(", "))
+            },
+            SymbolInternal::VarBind(slot) => write!(f, "{}", slot),
+            SymbolInternal::Template(template) => write!(f, "({})", template),
+            SymbolInternal::Parameter(name) => write!(f, "${}", name),
+            SymbolInternal::Wildcard => write!(f, "*"),
+        }
+    }
+}
+
+impl Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}", self.name)?;
+        match &self.slot_type {
+            SlotType::Text => (),
+            SlotType::List => write!(f, "...")?,
+            SlotType::Number => write!(f, ":number")?,
+            SlotType::Duration => write!(f, ":duration")?,
+            SlotType::Ordinal => write!(f, ":ordinal")?,
+            SlotType::Bool => write!(f, ":bool")?,
+            SlotType::Entity(entity) => write!(f, ":{{{}}}", entity)?,
+        }
+        if self.modifiers.lazy {
+            write!(f, ":lazy")?;
+        }
+        if self.modifiers.required {
+            write!(f, ":required")?;
+        }
+        if let Some((min, max)) = self.modifiers.words {
+            write!(f, ":{}..", min)?;
+            if let Some(max) = max {
+                write!(f, "{}", max)?;
+            }
+        }
+        write!(f, "]")
+    }
 }
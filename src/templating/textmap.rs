@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+/// Records which part of an original text each part of a rewritten text came from, so that positions in the
+/// rewritten text (such as where a slot matched) can be traced back to the original
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextMap {
+    // A byte range in the rewritten text, the byte range of the original text it came from, and whether it was
+    // copied unchanged, in order. Only copied pieces map byte for byte; other pieces only map as a whole.
+    pieces: Vec<(Range<usize>, Range<usize>, bool)>,
+}
+
+impl TextMap {
+    pub fn new() -> Self {
+        TextMap {
+            pieces: Vec::new(),
+        }
+    }
+
+    /// A map for text that was left as it was
+    pub fn identity(text: &str) -> Self {
+        let mut map = Self::new();
+        map.push_copied(0..text.len(), 0);
+        map
+    }
+
+    /// Records that `rewritten` came from rewriting `original`. Pieces must be pushed in order.
+    pub fn push(&mut self, rewritten: Range<usize>, original: Range<usize>) {
+        self.pieces.push((rewritten, original, false));
+    }
+
+    /// Records that `rewritten` is a copy of the original text starting at `original_start`. Pieces must be
+    /// pushed in order.
+    pub fn push_copied(&mut self, rewritten: Range<usize>, original_start: usize) {
+        let original = original_start..original_start + rewritten.len();
+        // Runs of copied text are kept as one piece, since text is often rewritten a character at a time
+        if let Some((last_rewritten, last_original, true)) = self.pieces.last_mut() {
+            if last_rewritten.end == rewritten.start && last_original.end == original.start {
+                last_rewritten.end = rewritten.end;
+                last_original.end = original.end;
+                return;
+            }
+        }
+        self.pieces.push((rewritten, original, true));
+    }
+
+    /// Returns the range of the original text that a range of the rewritten text came from, or `None` if the
+    /// range is empty or only covers text that was added (such as the spaces between rejoined words). A range
+    /// that covers part of a rewritten piece maps to all of the text that piece came from.
+    pub fn original_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        if range.is_empty() {
+            return None;
+        }
+        let mut result: Option<Range<usize>> = None;
+        for (rewritten, original, copied) in &self.pieces {
+            if rewritten.start >= range.end || rewritten.end <= range.start {
+                continue;
+            }
+            let mapped = if *copied {
+                let start = range.start.max(rewritten.start) - rewritten.start;
+                let end = range.end.min(rewritten.end) - rewritten.start;
+                original.start + start..original.start + end
+            } else {
+                original.clone()
+            };
+            result = Some(match result {
+                Some(r) => r.start.min(mapped.start)..r.end.max(mapped.end),
+                None => mapped,
+            });
+        }
+        result
+    }
+}
+
+/// Returns the byte range of every whitespace-separated word in the text
+pub fn word_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                ranges.push(s..i);
+                start = None;
+            },
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        ranges.push(s..text.len());
+    }
+    ranges
+}
@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use super::{matcher::TemplateMatcher, template::{SlotType, SymbolInternal, Template}};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationIssue {
+    #[error("Subtemplate \"{0}\" is used but never defined")]
+    UndefinedSubtemplate(String),
+    #[error("Subtemplates refer to each other in a cycle: {}", .0.join(" -> "))]
+    SubtemplateCycle(Vec<String>),
+    #[error("Subtemplate \"{0}\" is defined more than once")]
+    DuplicateSubtemplate(String),
+    #[error("Entity \"{0}\" is used but never defined")]
+    UndefinedEntity(String),
+    #[error("Entity \"{0}\" is defined more than once")]
+    DuplicateEntity(String),
+    #[error("Template \"{0}\" binds [{1}], which is not a parameter of its function")]
+    UnknownBinding(String, String),
+    #[error("Template \"{0}\" never binds parameter \"{1}\", which is not optional")]
+    UnboundParameter(String, String),
+    #[error("Template \"{0}\" can never match because \"{1}\" comes before it and matches the same inputs")]
+    Shadowed(String, String),
+}
+
+/// An `% example` or `% nonexample` line whose utterance did not behave as the template file says it should
+#[derive(Error, Debug, PartialEq)]
+pub enum ExampleFailure {
+    #[error("{0}: \"{1}\" did not match any template")]
+    NoMatch(String, String),
+    #[error("{0}: \"{1}\" matched \"{2}\" instead")]
+    WrongTemplate(String, String, String),
+    #[error("{0}: \"{1}\" bound [{2}] to \"{3}\" instead of \"{4}\"")]
+    WrongBinding(String, String, String, String, String),
+    #[error("{0}: \"{1}\" should not match, but it did")]
+    UnexpectedMatch(String, String),
+}
+
+/// Returns the names of the subtemplates called directly by a template (not by the subtemplates it calls)
+pub fn subtemplate_calls(template: &Template) -> Vec<&String> {
+    template
+        .all_symbols()
+        .into_iter()
+        .filter_map(|sym| match &sym.symbol {
+            SymbolInternal::SubtemplateCall(name) | SymbolInternal::ParameterizedCall(name, _) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds calls to subtemplates that are not defined, both in the given templates and in the subtemplates themselves
+pub fn find_undefined_subtemplates<'a>(templates: impl Iterator<Item = &'a Template>, matcher: &'a TemplateMatcher) -> Vec<ValidationIssue> {
+    let subtemplates = matcher.subtemplate_names().filter_map(|n| matcher.get_subtemplate(n));
+    let mut undefined: Vec<&String> = templates
+        .chain(subtemplates)
+        .flat_map(subtemplate_calls)
+        .filter(|name| matcher.get_subtemplate(name).is_none())
+        .collect();
+    undefined.sort();
+    undefined.dedup();
+    undefined.into_iter().map(|n| ValidationIssue::UndefinedSubtemplate(n.clone())).collect()
+}
+
+/// Returns the names of the entities used by a template's slots (not by the subtemplates it calls)
+pub fn entity_refs(template: &Template) -> Vec<&String> {
+    template
+        .all_symbols()
+        .into_iter()
+        .filter_map(|sym| match &sym.symbol {
+            SymbolInternal::VarBind(slot) => match &slot.slot_type {
+                SlotType::Entity(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds slots that use entities that are not defined, both in the given templates and in the subtemplates
+pub fn find_undefined_entities<'a>(templates: impl Iterator<Item = &'a Template>, matcher: &'a TemplateMatcher) -> Vec<ValidationIssue> {
+    let subtemplates = matcher.subtemplate_names().filter_map(|n| matcher.get_subtemplate(n));
+    let mut undefined: Vec<&String> = templates
+        .chain(subtemplates)
+        .flat_map(entity_refs)
+        .filter(|name| matcher.get_entity(name).is_none())
+        .collect();
+    undefined.sort();
+    undefined.dedup();
+    undefined.into_iter().map(|n| ValidationIssue::UndefinedEntity(n.clone())).collect()
+}
+
+/// Finds every cycle of subtemplates that (directly or indirectly) call themselves. Each cycle is reported once.
+pub fn find_subtemplate_cycles(matcher: &TemplateMatcher) -> Vec<ValidationIssue> {
+    let mut names: Vec<&String> = matcher.subtemplate_names().collect();
+    names.sort();
+
+    let mut issues = Vec::new();
+    let mut finished = HashSet::new();
+    for name in names {
+        let mut path = Vec::new();
+        visit_for_cycles(name, matcher, &mut path, &mut finished, &mut issues);
+    }
+    issues
+}
+
+fn visit_for_cycles<'a>(name: &'a String, matcher: &'a TemplateMatcher, path: &mut Vec<&'a String>, finished: &mut HashSet<&'a String>, issues: &mut Vec<ValidationIssue>) {
+    if finished.contains(name) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|n| *n == name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|n| (*n).clone()).collect();
+        cycle.push(name.clone());
+        issues.push(ValidationIssue::SubtemplateCycle(cycle));
+        return;
+    }
+    if let Some(template) = matcher.get_subtemplate(name) {
+        path.push(name);
+        for call in subtemplate_calls(template) {
+            visit_for_cycles(call, matcher, path, finished, issues);
+        }
+        path.pop();
+    }
+    finished.insert(name);
+}
@@ -0,0 +1,23 @@
+// Each test file only uses some of these
+#![allow(dead_code)]
+
+use std::{error::Error, fs, path::PathBuf, process};
+
+use homeboy::templating::{matcher::TemplateMatcher, parser::TemplateParser};
+
+/// A matcher with the subtemplates that the matching and generator tests share
+pub fn setup_matcher() -> Result<TemplateMatcher, Box<dyn Error>> {
+    let mut matcher = TemplateMatcher::new();
+
+    let pre_command_ask = TemplateParser::parse_template("(could|would) you please?")?;
+    matcher.add_subtemplate("pre command ask", pre_command_ask);
+    Ok(matcher)
+}
+
+/// An empty directory under the system temp directory, unique to this test run
+pub fn temp_dir(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = std::env::temp_dir().join(format!("homeboy_{}_{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
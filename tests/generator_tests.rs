@@ -0,0 +1,73 @@
+mod common;
+
+use std::error::Error;
+
+use common::setup_matcher;
+use cortex_lang::interpreting::interpreter::CortexInterpreter;
+use homeboy::templating::{generator::ExampleGenerator, handler::TemplateHandler, matcher::TemplateMatcher, parser::TemplateParser};
+
+#[test]
+fn example_generation_tests() -> Result<(), Box<dyn Error>> {
+    let mut matcher = setup_matcher()?;
+    let toggle = TemplateParser::parse_template("turn (on|off) the $device")?;
+    matcher.add_parameterized_subtemplate("toggle", vec![String::from("device")], toggle);
+
+    assert_examples("foo", vec!["foo"], &matcher)?;
+    assert_examples("foo bar?", vec!["foo bar", "foo"], &matcher)?;
+    assert_examples("(pause|stop) the music", vec!["pause the music", "stop the music"], &matcher)?;
+    assert_examples(
+        "{pre command ask}? play [song]",
+        vec![
+            "could you please play song",
+            "could you play song",
+            "would you please play song",
+            "would you play song",
+            "play song",
+        ],
+        &matcher
+    )?;
+    assert_examples("set a timer for [length:duration]", vec!["set a timer for five minutes"], &matcher)?;
+    assert_examples("{toggle(light|fan)}", vec!["turn on the light", "turn on the fan", "turn off the light", "turn off the fan"], &matcher)?;
+
+    Ok(())
+}
+
+#[test]
+fn example_generation_limit_and_filler_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+    let template = TemplateParser::parse_template("{pre command ask}? play [song] on? spotify?")?;
+
+    let examples = ExampleGenerator::new(&matcher, 3).generate(&template)?;
+    assert_eq!(3, examples.len());
+
+    let examples = ExampleGenerator::new(&matcher, 100)
+        .with_filler("song", "enter sandman")
+        .generate(&template)?;
+    assert!(examples.contains(&String::from("play enter sandman on spotify")));
+    // Every generated phrase is accepted by the template it came from
+    for example in &examples {
+        assert!(matcher.try_match(example, &template)?.is_some(), "{} did not match", example);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn handler_examples_tests() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/ranking_template_file.txt", &mut interpreter)?;
+
+    let examples = handler.examples(10)?;
+    assert_eq!(4, examples.len());
+    assert_eq!(("play [song] on spotify", vec![String::from("play song on spotify")]), examples[1]);
+
+    Ok(())
+}
+
+fn assert_examples(input: &str, expected: Vec<&str>, matcher: &TemplateMatcher) -> Result<(), Box<dyn Error>> {
+    let template = TemplateParser::parse_template(input)?;
+    let examples = ExampleGenerator::new(matcher, 100).generate(&template)?;
+    assert_eq!(expected, examples);
+    Ok(())
+}
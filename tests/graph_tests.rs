@@ -0,0 +1,79 @@
+mod common;
+
+use std::error::Error;
+
+use common::setup_matcher;
+use cortex_lang::interpreting::interpreter::CortexInterpreter;
+use homeboy::templating::{graph, handler::TemplateHandler, matcher::TemplateMatcher, parser::TemplateParser};
+
+#[test]
+fn dot_export_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_graph_matcher()?;
+    let templates = [
+        TemplateParser::parse_template("{pre command ask}? play [song] (on|in)=place {app}")?,
+        TemplateParser::parse_template("{toggle(light|{room} lamp)}? {missing}")?,
+    ];
+    let dot = graph::to_dot(
+        ["{pre command ask}? play [song] (on|in)=place {app}", "{toggle(light|{room} lamp)}? {missing}"].into_iter().zip(templates.iter()),
+        &matcher,
+    );
+
+    let expected = [
+        "digraph templates {",
+        r#"t0 [label="{pre command ask}? play [song] (on|in)=place {app}", shape=box];"#,
+        "t0 -> s1 [style=dashed];",
+        r#"t0_1 [label="[song]", shape=note];"#,
+        "t0 -> t0_1;",
+        r#"t0_2 [label="(on|in)=place", shape=diamond];"#,
+        "t0 -> t0_2;",
+        "t0 -> s0;",
+        "t1 -> s3 [style=dashed];",
+        "t1 -> s2;",
+        r#"t1_3 [label="{missing} (undefined)", shape=ellipse, color=red];"#,
+        r#"s0 [label="{app}", shape=ellipse];"#,
+        r#"s3 [label="{toggle(device)}", shape=ellipse];"#,
+        r#"s4 [label="{unused}", shape=ellipse, style=dashed, color=grey];"#,
+        "s3 -> s1 [style=dashed];",
+        "s4 -> s0;",
+    ];
+    for line in expected {
+        assert!(dot.contains(line), "missing {:?} in:\n{}", line, dot);
+    }
+    Ok(())
+}
+
+#[test]
+fn dead_subtemplate_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_graph_matcher()?;
+    let template = TemplateParser::parse_template("{toggle(light)}")?;
+    // "app" is called, but only by "unused", which is dead itself
+    assert_eq!(vec!["app", "room", "unused"], graph::dead_subtemplates([&template].into_iter(), &matcher));
+
+    let template = TemplateParser::parse_template("{toggle({room})} {unused}")?;
+    assert!(graph::dead_subtemplates([&template].into_iter(), &matcher).is_empty());
+    Ok(())
+}
+
+#[test]
+fn handler_dot_export_tests() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/parameterized_template_file.txt", &mut interpreter)?;
+
+    let dot = handler.to_dot();
+    assert!(dot.contains(r#"s1 [label="{toggle(device)}", shape=ellipse];"#));
+    assert!(dot.contains("t0 -> s1;"));
+    assert!(dot.contains("t1 -> s1;"));
+    assert!(dot.contains("s1 -> s0 [style=dashed];"));
+    Ok(())
+}
+
+// The shared subtemplates, plus some that call each other and one that nothing calls
+fn setup_graph_matcher() -> Result<TemplateMatcher, Box<dyn Error>> {
+    let mut matcher = setup_matcher()?;
+    matcher.add_subtemplate("app", TemplateParser::parse_template("spotify|youtube")?);
+    matcher.add_subtemplate("room", TemplateParser::parse_template("kitchen|bedroom")?);
+    matcher.add_subtemplate("unused", TemplateParser::parse_template("{app} please")?);
+    matcher.add_parameterized_subtemplate("toggle", vec![String::from("device")], TemplateParser::parse_template("{pre command ask}? turn [on:bool] the $device")?);
+    Ok(matcher)
+}
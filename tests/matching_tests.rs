@@ -1,6 +1,9 @@
+mod common;
+
 use std::error::Error;
 
-use homeboy::templating::{matcher::TemplateMatcher, parser::TemplateParser};
+use common::setup_matcher;
+use homeboy::templating::{entity::Entity, fuzzy::FuzzyOptions, matcher::{CompiledTemplateSet, TemplateError, TemplateMatcher}, parser::TemplateParser, slots::SlotValue};
 
 #[test]
 fn regex_generation_tests() -> Result<(), Box<dyn Error>> {
@@ -10,6 +13,13 @@ fn regex_generation_tests() -> Result<(), Box<dyn Error>> {
     assert_regex("foo?", "^(?:foo)?$", &matcher)?;
     assert_regex("(foo)?", "^(?:foo)?$", &matcher)?;
     assert_regex("[hello]", "^(?<hello>.*)$", &matcher)?;
+    assert_regex("(on|off)=state", "^(?<state>on|off)$", &matcher)?;
+    assert_regex("[task:lazy]", "^(?<task>.*?)$", &matcher)?;
+    assert_regex("[task:required]", r"^(?<task>\S.*)$", &matcher)?;
+    assert_regex("[task:1..8]", r"^(?<task>\S+(?:\s+\S+){0,7})$", &matcher)?;
+    assert_regex("[task:0..2:lazy]", r"^(?<task>(?:\S+(?:\s+\S+){0,1}?)??)$", &matcher)?;
+    assert_regex("* [song]", r"^(.*?)\s*(?<song>.*)$", &matcher)?;
+    assert_regex("{pre command ask}=ask?", r"^(?<ask>(?:could|would)\s*you\s*(?:please)?)?$", &matcher)?;
     assert_regex("{pre command ask}?", r"^(?:(?:could|would)\s*you\s*(?:please)?)?$", &matcher)?;
     assert_regex(
         "{pre command ask}? play [song] on Spotify", 
@@ -43,6 +53,237 @@ fn regex_matching_tests() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn compiled_set_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+    let templates = vec!["{pre command ask}? foo", "play [song]", "play [song] on spotify"]
+        .into_iter()
+        .map(|t| matcher.compile(&TemplateParser::parse_template(t)?).map_err(|e| e.into()))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let set = CompiledTemplateSet::new(templates)?;
+
+    assert_eq!(vec![0], set.candidates("could you foo"));
+    assert_eq!(vec![1, 2], set.candidates("play enter sandman on spotify"));
+    assert!(set.candidates("baz").is_empty());
+    let matched = set.get(2).unwrap().try_match("play enter sandman on spotify").unwrap();
+    assert_eq!("enter sandman", matched.get_binding("song").unwrap());
+
+    Ok(())
+}
+
+#[test]
+fn typed_slot_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+
+    assert_value("set volume to [level:number]", "set volume to twenty five", "level", SlotValue::Number(25.0), &matcher)?;
+    assert_value("set volume to [level:number]", "set volume to 40", "level", SlotValue::Number(40.0), &matcher)?;
+    assert_value("set volume to [level:number]", "set volume to one hundred and five", "level", SlotValue::Number(105.0), &matcher)?;
+    assert_value("set volume to [level:number]", "set volume to two point five", "level", SlotValue::Number(2.5), &matcher)?;
+    assert_no_match("set volume to [level:number]", "set volume to loud", &matcher)?;
+
+    assert_value("set a timer for [when:duration]", "set a timer for five minutes", "when", SlotValue::Number(300.0), &matcher)?;
+    assert_value("set a timer for [when:duration]", "set a timer for an hour and a half", "when", SlotValue::Number(5400.0), &matcher)?;
+    assert_value("set a timer for [when:duration]", "set a timer for 1 hour and 30 minutes", "when", SlotValue::Number(5400.0), &matcher)?;
+    assert_value("set a timer for [when:duration]", "set a timer for half an hour", "when", SlotValue::Number(1800.0), &matcher)?;
+    assert_no_match("set a timer for [when:duration]", "set a timer for later", &matcher)?;
+
+    assert_value("play the [n:ordinal] song", "play the third song", "n", SlotValue::Number(3.0), &matcher)?;
+    assert_value("play the [n:ordinal] song", "play the twenty first song", "n", SlotValue::Number(21.0), &matcher)?;
+    assert_value("play the [n:ordinal] song", "play the 12th song", "n", SlotValue::Number(12.0), &matcher)?;
+
+    assert_value("shuffle [on:bool]", "shuffle yes", "on", SlotValue::Boolean(true), &matcher)?;
+    assert_value("shuffle [on:bool]", "shuffle off", "on", SlotValue::Boolean(false), &matcher)?;
+    assert_no_match("shuffle [on:bool]", "shuffle maybe", &matcher)?;
+
+    let list = |items: &[&str]| SlotValue::List(items.iter().map(|i| String::from(*i)).collect());
+    assert_value("add [items...] to the list", "add milk, eggs and bread to the list", "items", list(&["milk", "eggs", "bread"]), &matcher)?;
+    assert_value("add [items...] to the list", "add milk, eggs, and peanut butter to the list", "items", list(&["milk", "eggs", "peanut butter"]), &matcher)?;
+    assert_value("queue [songs...]", "queue thunderstruck or back in black", "songs", list(&["thunderstruck", "back in black"]), &matcher)?;
+    assert_value("queue [songs...]", "queue thunderstruck", "songs", list(&["thunderstruck"]), &matcher)?;
+
+    Ok(())
+}
+
+#[test]
+fn fuzzy_matching_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+    let options = FuzzyOptions::default();
+
+    let template = matcher.compile(&TemplateParser::parse_template("{pre command ask}? play [song] on spotify")?)?;
+    let matched = template.try_fuzzy_match("could you play enter sandman on spotted fie", &options).unwrap();
+    assert_eq!("enter sandman", matched.get_binding("song").unwrap());
+    assert_eq!(1, matched.corrections().len());
+    assert_eq!("spotted fie", matched.corrections()[0].heard);
+    assert_eq!("spotify", matched.corrections()[0].corrected);
+
+    let matched = template.try_fuzzy_match("culd you play enter sandman on spotfy", &options).unwrap();
+    assert_eq!(2, matched.corrections().len());
+
+    // Words inside a slot are left as they were heard
+    let matched = template.try_fuzzy_match("play spotty on spotfy", &options).unwrap();
+    assert_eq!("spotty", matched.get_binding("song").unwrap());
+    assert_eq!(1, matched.corrections().len());
+
+    let template = matcher.compile(&TemplateParser::parse_template("pause the music")?)?;
+    let matched = template.try_fuzzy_match("paws the music", &options).unwrap();
+    assert_eq!("pause", matched.corrections()[0].corrected);
+    assert!(template.try_fuzzy_match("play the music", &options).is_none());
+    assert!(template.try_match("paws the music").is_none());
+
+    let matched = template.try_fuzzy_match("pause the music", &options).unwrap();
+    assert!(matched.corrections().is_empty());
+
+    // Spans are within the input as heard, not the corrected input
+    let template = matcher.compile(&TemplateParser::parse_template("play [song] on spotify [device]")?)?;
+    let matched = template.try_fuzzy_match("play enter sandman  on spotted fie kitchen speaker", &options).unwrap();
+    assert_eq!(Some(5..18), matched.get_span("song"));
+    assert_eq!(Some(35..50), matched.get_span("device"));
+
+    Ok(())
+}
+
+#[test]
+fn entity_slot_tests() -> Result<(), Box<dyn Error>> {
+    let mut matcher = setup_matcher()?;
+    let mut rooms = Entity::new();
+    rooms.add_line("kitchen");
+    rooms.add_line("living room: lounge, front room");
+    matcher.add_entity("rooms", rooms);
+
+    let template = "turn on the [room:{rooms}] light";
+    assert_value(template, "turn on the kitchen light", "room", SlotValue::Text(String::from("kitchen")), &matcher)?;
+    assert_value(template, "turn on the front room light", "room", SlotValue::Text(String::from("living room")), &matcher)?;
+    assert_no_match(template, "turn on the garage light", &matcher)?;
+
+    // Entity values can be corrected by fuzzy matching
+    let compiled = matcher.compile(&TemplateParser::parse_template(template)?)?;
+    let matched = compiled.try_fuzzy_match("turn on the kichen light", &FuzzyOptions::default()).unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("kitchen"))), matched.get_value("room"));
+    assert_eq!(1, matched.corrections().len());
+
+    let template = TemplateParser::parse_template("turn on the [room:{devices}]")?;
+    assert_eq!(Err(TemplateError::EntityNotFound(String::from("devices"))), matcher.convert_template_to_regex(&template));
+
+    Ok(())
+}
+
+#[test]
+fn parameterized_subtemplate_tests() -> Result<(), Box<dyn Error>> {
+    let mut matcher = setup_matcher()?;
+    let toggle = TemplateParser::parse_template("turn [on:bool] the $device")?;
+    matcher.add_parameterized_subtemplate("toggle", vec![String::from("device")], toggle);
+    let nested = TemplateParser::parse_template("{pre command ask}? {toggle($thing)}")?;
+    matcher.add_parameterized_subtemplate("ask toggle", vec![String::from("thing")], nested);
+
+    // Slots inside the subtemplate are bound by the calling template
+    assert_value("{toggle(light|lamp)} please?", "turn on the lamp", "on", SlotValue::Boolean(true), &matcher)?;
+    assert_no_match("{toggle(light|lamp)} please?", "turn on the fan", &matcher)?;
+    assert_match("{toggle([device])}", "turn off the kitchen fan", vec![("on", "off"), ("device", "kitchen fan")], &matcher)?;
+    assert_match("{ask toggle(fan)}", "could you turn off the fan", vec![("on", "off")], &matcher)?;
+
+    let template = TemplateParser::parse_template("{toggle}")?;
+    assert_eq!(Err(TemplateError::WrongArgumentCount(String::from("toggle"), 1, 0)), matcher.convert_template_to_regex(&template));
+    let template = TemplateParser::parse_template("{toggle(light, fan)}")?;
+    assert_eq!(Err(TemplateError::WrongArgumentCount(String::from("toggle"), 1, 2)), matcher.convert_template_to_regex(&template));
+    let template = TemplateParser::parse_template("turn on the $device")?;
+    assert_eq!(Err(TemplateError::UnknownParameter(String::from("device"))), matcher.convert_template_to_regex(&template));
+
+    Ok(())
+}
+
+#[test]
+fn span_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+
+    let template = TemplateParser::parse_template("{pre command ask}? play [song] on (spotify|youtube)=app")?;
+    let matched = matcher.try_match("could you play  enter sandman  on youtube", &template)?.unwrap();
+    assert_eq!(Some(16..29), matched.get_span("song"));
+    assert_eq!(Some(34..41), matched.get_span("app"));
+    assert_eq!(None, matched.get_span("missing"));
+
+    let template = TemplateParser::parse_template("play [song]")?;
+    let matched = matcher.try_match("play ", &template)?.unwrap();
+    assert_eq!(Some(5..5), matched.get_span("song"));
+
+    Ok(())
+}
+
+#[test]
+fn labeled_group_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+
+    assert_match("turn (on|off)=state the light", "turn off the light", vec![("state", "off")], &matcher)?;
+    assert_match("{pre command ask}=ask? play [song]", "would you play jazz", vec![("ask", "would you"), ("song", "jazz")], &matcher)?;
+    // A labeled group that was skipped binds nothing
+    assert_match("{pre command ask}=ask? play [song]", "play jazz", vec![("song", "jazz")], &matcher)?;
+    assert_value("turn (on|off)=state the light", "turn on the light", "state", SlotValue::Text(String::from("on")), &matcher)?;
+    assert_eq!(vec!["state", "song"], matcher.slot_names(&TemplateParser::parse_template("turn (on|off)=state [song]")?)?);
+
+    // The words of a labeled group are still template text, so they count as literal words
+    let template = TemplateParser::parse_template("turn (on|off)=state the light")?;
+    let matched = matcher.try_match("turn on the light", &template)?.unwrap();
+    assert_eq!(4, matched.literal_words());
+    assert_eq!(0, matched.wildcard_chars());
+
+    Ok(())
+}
+
+#[test]
+fn slot_modifier_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+
+    assert_match("remind me to [task] at [time]", "remind me to meet anna at the station at noon", vec![("task", "meet anna at the station"), ("time", "noon")], &matcher)?;
+    assert_match("remind me to [task:lazy] at [time]", "remind me to meet anna at the station at noon", vec![("task", "meet anna"), ("time", "the station at noon")], &matcher)?;
+
+    assert_match("play [song:1..3]", "play bohemian rhapsody", vec![("song", "bohemian rhapsody")], &matcher)?;
+    assert_no_match("play [song:1..3]", "play the song that never ends", &matcher)?;
+    assert_no_match("play [song:2..]", "play yesterday", &matcher)?;
+    assert_match("remind me to [task:1..2] at [time]", "remind me to eat lunch at work at noon", vec![("task", "eat lunch"), ("time", "work at noon")], &matcher)?;
+
+    assert_match("play [song]", "play ", vec![("song", "")], &matcher)?;
+    assert_no_match("play [song:required]", "play ", &matcher)?;
+    assert_no_match("play [song:required]", "play", &matcher)?;
+
+    Ok(())
+}
+
+#[test]
+fn wildcard_tests() -> Result<(), Box<dyn Error>> {
+    let matcher = setup_matcher()?;
+
+    assert_match("* play [song]", "hey could you maybe play jazz", vec![("song", "jazz")], &matcher)?;
+    assert_match("turn on the * light", "turn on the big kitchen light", vec![], &matcher)?;
+    assert_match("turn on the * light", "turn on the light", vec![], &matcher)?;
+    assert_no_match("turn on the * light", "turn on the fan", &matcher)?;
+
+    // Filler words are not template text, so they don't count as literal words
+    let template = TemplateParser::parse_template("turn on the * light")?;
+    let matched = matcher.try_match("turn on the big kitchen light", &template)?.unwrap();
+    assert_eq!(4, matched.literal_words());
+    assert_eq!("big kitchen".len(), matched.wildcard_chars());
+
+    Ok(())
+}
+
+#[test]
+fn recursive_subtemplate_tests() -> Result<(), Box<dyn Error>> {
+    let mut matcher = setup_matcher()?;
+    matcher.add_subtemplate("loop", TemplateParser::parse_template("again {loop}?")?);
+
+    let template = TemplateParser::parse_template("{loop}")?;
+    assert_eq!(Err(TemplateError::RecursiveSubtemplate(String::from("loop"))), matcher.convert_template_to_regex(&template));
+    assert!(matcher.compile(&template).is_err());
+
+    Ok(())
+}
+
+fn assert_value(input: &str, statement: &str, name: &str, expected: SlotValue, matcher: &TemplateMatcher) -> Result<(), Box<dyn Error>> {
+    let template = TemplateParser::parse_template(input)?;
+    let matched = matcher.try_match(statement, &template)?.unwrap();
+    assert_eq!(Some(&expected), matched.get_value(name));
+    Ok(())
+}
+
 fn assert_regex(input_template: &str, expected_regex: &str, matcher: &TemplateMatcher) -> Result<(), Box<dyn Error>> {
     let template = TemplateParser::parse_template(input_template)?;
     let regex = matcher.convert_template_to_regex(&template)?;
@@ -67,11 +308,3 @@ fn assert_no_match(input: &str, statement: &str, matcher: &TemplateMatcher) -> R
     assert!(matched.is_none());
     Ok(())
 }
-
-fn setup_matcher() -> Result<TemplateMatcher, Box<dyn Error>> {
-    let mut matcher = TemplateMatcher::new();
-
-    let pre_command_ask = TemplateParser::parse_template("(could|would) you please?")?;
-    matcher.add_subtemplate("pre command ask", pre_command_ask);
-    Ok(matcher)
-}
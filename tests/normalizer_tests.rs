@@ -0,0 +1,95 @@
+use std::error::Error;
+
+use homeboy::templating::{matcher::TemplateMatcher, normalizer::Normalizer, parser::TemplateParser, slots::SlotValue};
+
+#[test]
+fn normalization_tests() {
+    let all = Normalizer {
+        contractions: true,
+        number_words: true,
+        accents: true,
+    };
+    assert_eq!("what is the weather", all.normalize("what's the weather"));
+    assert_eq!("do not stop", all.normalize("don't stop"));
+    assert_eq!("can not", all.normalize("can't"));
+    assert_eq!("john's car", all.normalize("john's car"));
+    assert_eq!("cafe creme", all.normalize("café crème"));
+    assert_eq!("turn it up to 11", all.normalize("turn it up to eleven"));
+    assert_eq!("set volume to 105", all.normalize("set volume to one hundred and five"));
+    assert_eq!("rock and roll", all.normalize("rock and roll"));
+    assert_eq!("play the twenty first song", all.normalize("play the twenty first song"));
+
+    // Nothing changes unless a step is enabled
+    assert_eq!("What's  up", Normalizer::default().normalize("What's  up"));
+}
+
+#[test]
+fn normalization_map_tests() {
+    let all = Normalizer {
+        contractions: true,
+        number_words: true,
+        accents: true,
+    };
+    let input = "What's  up in   the Café at twenty five past";
+    let (normalized, map) = all.normalize_mapped(input);
+    assert_eq!("what is up in the Cafe at 25 past", normalized);
+    // Expanded words and joined numbers map back to everything they came from
+    assert_eq!(Some(0..6), map.original_range(0..4));
+    assert_eq!(Some(0..6), map.original_range(5..7));
+    assert_eq!(Some(20..25), map.original_range(18..22));
+    assert_eq!(Some(29..40), map.original_range(26..28));
+    assert_eq!(Some(8..40), map.original_range(8..28));
+    assert_eq!(None, map.original_range(4..5));
+
+    // Text that isn't normalized maps onto itself
+    let (normalized, map) = Normalizer::default().normalize_mapped(input);
+    assert_eq!(input, normalized);
+    assert_eq!(Some(3..9), map.original_range(3..9));
+}
+
+#[test]
+fn normalized_matching_tests() -> Result<(), Box<dyn Error>> {
+    let mut matcher = TemplateMatcher::new();
+    matcher.set_normalizer(Normalizer {
+        contractions: true,
+        number_words: true,
+        accents: true,
+    });
+
+    let template = TemplateParser::parse_template("what's the weather in [city]")?;
+    let matched = matcher.try_match("what is the weather in paris", &template)?.unwrap();
+    assert_eq!("paris", matched.get_binding("city").unwrap());
+    assert!(matcher.try_match("what's the weather in paris", &template)?.is_some());
+
+    let template = TemplateParser::parse_template("turn it up to eleven")?;
+    assert!(matcher.try_match("turn it up to 11", &template)?.is_some());
+    let template = TemplateParser::parse_template("set volume to twenty five")?;
+    assert!(matcher.try_match("set volume to twenty five", &template)?.is_some());
+    assert!(matcher.try_match("set volume to 25", &template)?.is_some());
+
+    let template = TemplateParser::parse_template("play música")?;
+    assert!(matcher.try_match("play musica", &template)?.is_some());
+
+    // Slots bind the text as it was given, while typed slots are converted from the normalized text
+    let template = TemplateParser::parse_template("play [song]")?;
+    let matched = matcher.try_match("play we can't stop", &template)?.unwrap();
+    assert_eq!("we can't stop", matched.get_binding("song").unwrap());
+    assert_eq!(Some(&SlotValue::Text(String::from("we can't stop"))), matched.get_value("song"));
+    let template = TemplateParser::parse_template("set volume to [level:number]")?;
+    let matched = matcher.try_match("set volume to twenty five", &template)?.unwrap();
+    assert_eq!("twenty five", matched.get_binding("level").unwrap());
+    assert_eq!(Some(&SlotValue::Number(25.0)), matched.get_value("level"));
+
+    // Folded letters that keep the word's length map back to the whole word rather than splitting a character
+    let mut matcher = TemplateMatcher::new();
+    matcher.set_normalizer(Normalizer::standard());
+    let template = TemplateParser::parse_template("[x] se")?;
+    assert_eq!("straße", matcher.try_match("straße", &template)?.unwrap().get_binding("x").unwrap());
+    let template = TemplateParser::parse_template("play [x]")?;
+    assert_eq!("cæsar œuvre", matcher.try_match("play cæsar œuvre", &template)?.unwrap().get_binding("x").unwrap());
+
+    // Spans are within the normalized input
+    assert_eq!(Some(14..16), matched.get_span("level"));
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use std::{error::Error, fs::{self, File}, path::PathBuf, time::{Duration, SystemTime}};
+
+use common::temp_dir;
+use homeboy::runner::runner::CommandRunner;
+
+// Writes the file with a modification time later than any before it, since a rewrite within the same clock tick
+// would otherwise go unnoticed
+fn write_later(path: &PathBuf, contents: &str, seconds: u64) -> Result<(), Box<dyn Error>> {
+    fs::write(path, contents)?;
+    File::options().write(true).open(path)?.set_modified(SystemTime::now() + Duration::from_secs(seconds))?;
+    Ok(())
+}
+
+#[test]
+fn test_check_templates_without_services() -> Result<(), Box<dyn Error>> {
+    // Checking templates must not need credentials or a memory file, even when templates use those services
+    let mut runner = CommandRunner::new()?;
+    let failures = runner.check_templates("./tests/res/services_template_file.txt")?;
+    assert!(failures.is_empty());
+
+    let mut runner = CommandRunner::new()?;
+    let graph = runner.template_graph("./tests/res/services_template_file.txt")?;
+    assert!(graph.contains("turn on the"));
+    Ok(())
+}
+
+#[test]
+fn test_failed_reload() -> Result<(), Box<dyn Error>> {
+    let dir = temp_dir("reload")?;
+    let main = dir.join("main.txt");
+    let extra = dir.join("extra.txt");
+    let pause = "% temp\npause\nfn ~(): void {\n}\n% end\n";
+    write_later(&main, pause, 0)?;
+
+    let mut runner = CommandRunner::new()?;
+    runner.load_templates(main.to_str().unwrap())?;
+    assert!(runner.handler().find_function("pause")?.is_some());
+
+    // The new include doesn't load, so the previous templates are kept
+    write_later(&main, &format!("% include extra.txt\n{}", pause), 10)?;
+    write_later(&extra, "% temp\nstop\nfn ~(): void {\n    undefined();\n}\n% end\n", 10)?;
+    assert!(runner.reload_templates().is_err());
+    assert!(runner.handler().find_function("pause")?.is_some());
+    assert!(runner.handler().find_function("stop")?.is_none());
+
+    // Fixing only the included file, which the previous templates didn't use, is picked up on the next command
+    write_later(&extra, "% temp\nstop\nfn ~(): void {\n}\n% end\n", 20)?;
+    runner.run("pause")?;
+    assert!(runner.handler().find_function("stop")?.is_some());
+    assert!(runner.handler().find_function("pause")?.is_some());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_ambiguous_compound_command() -> Result<(), Box<dyn Error>> {
+    let mut runner = CommandRunner::new()?;
+    runner.load_templates("./tests/res/ambiguous_template_file.txt")?;
+    runner.set_compound_splitting(true);
+
+    // The first ambiguous part asks which template was meant, and the parts after it wait for the answer
+    runner.run("play the daily and then play the daily and then pause")?;
+    let choice = runner.pending_choice().unwrap();
+    assert_eq!("play the daily", choice.input);
+    assert_eq!(2, choice.options.len());
+    assert_eq!(vec![(2, String::from("play the daily")), (3, String::from("pause"))], choice.remaining);
+
+    // Once answered, the next part runs and asks its own question
+    runner.run("the podcast")?;
+    let choice = runner.pending_choice().unwrap();
+    assert_eq!(vec![(3, String::from("pause"))], choice.remaining);
+
+    runner.run("the first one")?;
+    assert!(runner.pending_choice().is_none());
+    Ok(())
+}
@@ -1,7 +1,7 @@
-use std::error::Error;
+use std::{cell::RefCell, error::Error, rc::Rc};
 
 use cortex_lang::interpreting::interpreter::CortexInterpreter;
-use homeboy::templating::handler::TemplateHandler;
+use homeboy::templating::{handler::{TemplateHandler, TemplateHandlerError}, normalizer::{self, Normalizer}, slots::SlotValue, validation::{ExampleFailure, ValidationIssue}};
 
 #[test]
 fn test_template_loader() -> Result<(), Box<dyn Error>> {
@@ -10,3 +10,432 @@ fn test_template_loader() -> Result<(), Box<dyn Error>> {
     handler.load_from_file("./tests/res/test_template_file.txt", &mut interpreter)?;
     Ok(())
 }
+
+#[test]
+fn test_best_match_ranking() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/ranking_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("play enter sandman on spotify")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+    assert_eq!(3, result.score.literal_words);
+    assert_eq!("enter sandman".len(), result.score.wildcard_chars);
+
+    let result = handler.find_function("play enter sandman")?.unwrap();
+    assert_eq!("thing", result.function.get_param(0).unwrap());
+
+    // Explicit priority outranks the better literal match
+    let result = handler.find_function("shuffle on spotify")?.unwrap();
+    assert_eq!(5, result.score.priority);
+    assert_eq!(1, result.function.num_params());
+
+    Ok(())
+}
+
+#[test]
+fn test_suggestions() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/suggestions_template_file.txt", &mut interpreter)?;
+
+    assert!(handler.find_function("turn the lights blue")?.is_none());
+    // Only the closest phrasing of each template is suggested, and disabled templates are left out
+    assert_eq!(vec!["turn on the lights"], handler.suggestions("turn the lights blue", 3)?);
+    assert_eq!(vec!["set a timer for five minutes", "play song on spotify"], handler.suggestions("play a timer", 3)?);
+    assert_eq!(vec!["set a timer for five minutes"], handler.suggestions("play a timer", 1)?);
+    assert!(handler.suggestions("hello there", 3)?.is_empty());
+
+    assert_eq!(2, handler.get_fallback()?.unwrap().num_params());
+    assert_eq!(2, handler.get_fallback_param_types().len());
+    Ok(())
+}
+
+#[test]
+fn test_compound_utterances() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/compound_template_file.txt", &mut interpreter)?;
+
+    let split = |input: &str| handler.split_compound(input).map(|parts| parts.into_iter().map(|p| String::from(&input[p])).collect::<Vec<_>>());
+    assert_eq!(Some(vec![String::from("pause the music"), String::from("tell me the weather")]), split("pause the music and then tell me the weather"));
+    assert_eq!(Some(vec![String::from("turn off the lights"), String::from("pause the music"), String::from("tell me the weather")]), split("turn off the lights and pause the music after that tell me the weather"));
+    // Parts that match nothing on their own are kept with the part before them
+    assert_eq!(Some(vec![String::from("play rock and roll"), String::from("turn on the lights")]), split("play rock and roll then turn on the lights"));
+    assert_eq!(None, split("play rock and roll"));
+    assert_eq!(None, split("pause the music and dance"));
+    assert_eq!(None, split("tell me the weather"));
+    Ok(())
+}
+
+#[test]
+fn test_ambiguous_matches() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/ambiguous_template_file.txt", &mut interpreter)?;
+
+    let candidates = handler.find_candidates("play the daily")?;
+    assert_eq!(2, candidates.len());
+    assert_eq!("music", candidates[0].function.get_param(0).unwrap());
+    assert_eq!("podcast", candidates[1].function.get_param(0).unwrap());
+    // `find_function` still breaks the tie by file order
+    assert_eq!("music", handler.find_function("play the daily")?.unwrap().function.get_param(0).unwrap());
+    assert_eq!(1, handler.find_candidates("pause")?.len());
+    assert!(handler.find_candidates("stop")?.is_empty());
+
+    let options: Vec<String> = candidates.iter().map(|c| handler.describe(c)).collect::<Result<_, _>>()?;
+    assert_eq!(vec!["play music", "play podcast"], options);
+    assert_eq!(Some(1), handler.choose("the podcast", &options));
+    assert_eq!(Some(0), handler.choose("music please", &options));
+    assert_eq!(Some(0), handler.choose("the first one", &options));
+    assert_eq!(Some(1), handler.choose("the 2nd", &options));
+    assert_eq!(Some(1), handler.choose("the last one", &options));
+    // Words that every option shares do not pick one out
+    assert_eq!(None, handler.choose("play", &options));
+    assert_eq!(None, handler.choose("the third one", &options));
+    assert_eq!(None, handler.choose("never mind", &options));
+    Ok(())
+}
+
+#[test]
+fn test_tags() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/tagged_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("play jazz radio")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+    // Templates with enabled=false are never chosen
+    assert!(handler.find_function("stop everything")?.is_none());
+
+    handler.set_tag_enabled("radio", false);
+    assert!(!handler.is_tag_enabled("radio"));
+    let result = handler.find_function("play jazz radio")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+
+    // Disabling any one of a template's tags disables it
+    handler.set_tag_enabled("music", false);
+    let result = handler.find_function("play jazz radio")?.unwrap();
+    assert_eq!("thing", result.function.get_param(0).unwrap());
+    let explanations = handler.explain("play jazz radio")?;
+    assert!(explanations[0].matched() && !explanations[0].enabled);
+    assert!(explanations[2].selected && explanations[2].enabled);
+
+    handler.set_tag_enabled("music", true);
+    handler.set_tag_enabled("radio", true);
+    let result = handler.find_function("play jazz radio")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_exceptions() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/except_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("play yellow submarine")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+    // The excluded input moves on to the next template that matches, if there is one
+    let result = handler.find_function("play the weather report")?.unwrap();
+    assert_eq!(0, result.function.num_params());
+    assert!(handler.find_function("play the weather")?.is_none());
+    assert!(handler.find_function("play yellow submarine backwards")?.is_none());
+    assert!(handler.find_function("play nothing")?.is_none());
+    assert!(handler.check().is_empty());
+
+    let explanations = handler.explain("play the weather")?;
+    assert!(explanations[0].matched() && explanations[0].excluded && !explanations[0].selected);
+    Ok(())
+}
+
+#[test]
+fn test_exceptions_do_not_shadow() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    // The second template has the same shape as the first, but still gets the inputs the first rules out
+    handler.load_from_file("./tests/res/except_shadow_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("play the weather report")?.unwrap();
+    assert_eq!("thing", result.function.get_param(0).unwrap());
+    let result = handler.find_function("play yellow submarine")?.unwrap();
+    assert_eq!("song", result.function.get_param(0).unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_input_spans() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.set_normalizer(Normalizer::standard())?;
+    handler.load_from_file("./tests/res/ranking_template_file.txt", &mut interpreter)?;
+
+    // The normalized input has single spaces, but spans are given within the input as it was passed in
+    let input = "play  they're   coming on spotify";
+    let result = handler.find_function(input)?.unwrap();
+    assert_eq!(Some(5..20), result.match_inst.get_span("song"));
+    assert_eq!("they're   coming", &input[result.input_span("song").unwrap()]);
+    // Normalizing only helps matching, so the bound text is the text as it was passed in
+    assert_eq!("they're   coming", result.match_inst.get_binding("song").unwrap());
+    assert_eq!(Some(&SlotValue::Text(String::from("they're   coming"))), result.match_inst.get_value("song"));
+
+    let result = handler.find_function("play beyoncé")?.unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("beyoncé"))), result.match_inst.get_value("thing"));
+    let result = handler.find_function("play straße")?.unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("straße"))), result.match_inst.get_value("thing"));
+    Ok(())
+}
+
+#[test]
+fn test_explain() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/ranking_template_file.txt", &mut interpreter)?;
+
+    let explanations = handler.explain("play enter sandman on spotify")?;
+    assert_eq!(4, explanations.len());
+    assert!(explanations[0].matched() && !explanations[0].selected);
+    assert!(explanations[1].matched() && explanations[1].selected);
+    assert_eq!(vec![(String::from("song"), String::from("enter sandman"))], explanations[1].bindings);
+    assert_eq!(Some(String::new()), explanations[2].matched_prefix);
+
+    let explanations = handler.explain("shuffle on youtube")?;
+    assert!(explanations[2].selected);
+    assert_eq!(Some(String::from("shuffle on")), explanations[3].matched_prefix);
+    assert!(explanations[3].regex.starts_with("^shuffle"));
+
+    // A slot reached partway through takes only the words it needs, so the prefix stops where the input went wrong
+    let explanations = handler.explain("play enter sandman on youtube")?;
+    assert_eq!(Some(String::from("play enter sandman on")), explanations[1].matched_prefix);
+    let explanations = handler.explain("play thunderstruck")?;
+    assert_eq!(Some(String::from("play thunderstruck")), explanations[1].matched_prefix);
+    Ok(())
+}
+
+#[test]
+fn test_examples() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    let path = "./tests/res/examples_template_file.txt";
+    handler.load_from_file(path, &mut interpreter)?;
+
+    let location = |line: usize| format!("{}:{}", path, line);
+    assert_eq!(vec![
+        ExampleFailure::WrongTemplate(location(4), String::from("play enter sandman on spotify"), String::from("play [song] on spotify")),
+        ExampleFailure::WrongBinding(location(12), String::from("play enter sandman on spotify"), String::from("song"), String::from("enter sandman"), String::from("sandman")),
+        ExampleFailure::UnexpectedMatch(location(14), String::from("play enter sandman on spotify")),
+        ExampleFailure::NoMatch(location(23), String::from("turn it up")),
+    ], handler.check());
+    Ok(())
+}
+
+#[test]
+fn test_sanitized_examples() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    // Examples are written as transcripts, with capitals and punctuation that are stripped before matching
+    handler.load_from_file("./tests/res/sanitized_examples_template_file.txt", &mut interpreter)?;
+    assert!(handler.check().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_entities() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/entity_template_file.txt", &mut interpreter)?;
+    assert!(handler.check().is_empty());
+
+    let result = handler.find_function("turn on the front room light")?.unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("living room"))), result.match_inst.get_value("room"));
+    let result = handler.find_function("turn on the garage light")?.unwrap();
+    assert_eq!("garage light", result.match_inst.get_binding("thing").unwrap());
+
+    let issues = load_issues("./tests/res/invalid_entity_file.txt")?;
+    assert_eq!(vec![
+        ValidationIssue::DuplicateEntity(String::from("rooms")),
+        ValidationIssue::UndefinedEntity(String::from("devices")),
+    ], issues);
+    Ok(())
+}
+
+#[test]
+fn test_memory_entities() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    let rooms = Rc::new(RefCell::new(vec![String::from("garage"), String::from("attic: loft")]));
+    let remembered = rooms.clone();
+    handler.set_entity_resolver(Some(Box::new(move |key| match key {
+        "rooms" => Some(remembered.borrow().clone()),
+        _ => None,
+    })));
+    handler.load_from_file("./tests/res/memory_entity_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("turn on the loft light")?.unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("attic"))), result.match_inst.get_value("room"));
+    assert!(handler.find_function("turn on the kitchen light")?.is_some());
+    assert!(handler.find_function("turn on the cellar light")?.is_none());
+    assert!(!handler.refresh_entities()?);
+
+    // Values remembered after loading are picked up once the entities are refreshed
+    rooms.borrow_mut().push(String::from("cellar"));
+    assert!(handler.refresh_entities()?);
+    assert!(handler.find_function("turn on the cellar light")?.is_some());
+    assert!(handler.find_function("turn on the kitchen light")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn test_parameterized_subtemplates() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/parameterized_template_file.txt", &mut interpreter)?;
+
+    let result = handler.find_function("could you turn off the bedroom lamp")?.unwrap();
+    assert_eq!(Some(&SlotValue::Boolean(false)), result.match_inst.get_value("on"));
+    assert_eq!("bedroom", result.match_inst.get_binding("room").unwrap());
+    assert!(handler.find_function("turn on the fan")?.is_some());
+    assert!(handler.find_function("turn on the bedroom fan")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_invalid_subtemplates() -> Result<(), Box<dyn Error>> {
+    let issues = load_issues("./tests/res/invalid_subtemplates_file.txt")?;
+    assert_eq!(vec![
+        ValidationIssue::DuplicateSubtemplate(String::from("third")),
+        ValidationIssue::UndefinedSubtemplate(String::from("greeting")),
+        ValidationIssue::SubtemplateCycle(vec![String::from("first"), String::from("second"), String::from("first")]),
+    ], issues);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_templates() -> Result<(), Box<dyn Error>> {
+    let issues = load_issues("./tests/res/invalid_templates_file.txt")?;
+    assert_eq!(vec![
+        ValidationIssue::UnknownBinding(String::from("play [song]"), String::from("song")),
+        ValidationIssue::UnboundParameter(String::from("pause [thing]?"), String::from("device")),
+        ValidationIssue::Shadowed(String::from("play [track]"), String::from("play [song]")),
+    ], issues);
+    Ok(())
+}
+
+fn load_issues(path: &str) -> Result<Vec<ValidationIssue>, Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    let error = handler.load_from_file(path, &mut interpreter).err().unwrap();
+    match *error.downcast::<TemplateHandlerError>()? {
+        TemplateHandlerError::Invalid(issues) => Ok(issues),
+        other => Err(Box::new(other)),
+    }
+}
+
+#[test]
+fn test_includes() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    // The shared subtemplates are included twice, but only loaded once
+    handler.load("./tests/res/include/main.txt", &mut interpreter)?;
+    assert_eq!(3, handler.sources().len());
+
+    assert!(handler.find_function("could you pause")?.is_some());
+    let result = handler.find_function("can you play thunderstruck")?.unwrap();
+    assert_eq!("thunderstruck", result.match_inst.get_binding("song").unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_include_cycle() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    let error = handler.load("./tests/res/include_cycle/first.txt", &mut interpreter).err().unwrap();
+    match *error.downcast::<TemplateHandlerError>()? {
+        TemplateHandlerError::Located { path, line, column: _, block: _, error } => {
+            assert_eq!("./tests/res/include_cycle/second.txt", path);
+            assert_eq!(1, line);
+            assert!(matches!(error.downcast_ref::<TemplateHandlerError>(), Some(TemplateHandlerError::IncludeCycle(files)) if files.len() == 3));
+            Ok(())
+        },
+        other => Err(Box::new(other)),
+    }
+}
+
+#[test]
+fn test_template_directory() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load("./tests/res/template_dir", &mut interpreter)?;
+
+    let result = handler.find_function("could you play thunderstruck")?.unwrap();
+    assert_eq!("thunderstruck", result.match_inst.get_binding("song").unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_error_locations() -> Result<(), Box<dyn Error>> {
+    assert_location("./tests/res/malformed/bad_header.txt", 8, Some(6), Some("% temp"))?;
+    assert_location("./tests/res/malformed/bad_function.txt", 9, None, Some("% temp"))?;
+    assert_location("./tests/res/malformed/bad_subtemplate.txt", 4, Some(5), Some("% sub"))?;
+    assert_location("./tests/res/malformed/illegal_line.txt", 6, None, None)?;
+    assert_location("./tests/res/malformed/bad_example.txt", 3, None, Some("% temp"))?;
+    assert_location("./tests/res/malformed/bad_attribute.txt", 7, None, Some("% temp"))?;
+    assert_location("./tests/res/malformed/bad_except.txt", 3, Some(15), Some("% temp"))?;
+    Ok(())
+}
+
+fn assert_location(path: &str, expected_line: usize, expected_column: Option<usize>, expected_block: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    let error = handler.load_from_file(path, &mut interpreter).err().unwrap();
+    match *error.downcast::<TemplateHandlerError>()? {
+        TemplateHandlerError::Located { path: error_path, line, column, block, error: _ } => {
+            assert_eq!(path, error_path);
+            assert_eq!(expected_line, line);
+            assert_eq!(expected_column, column);
+            assert_eq!(expected_block, block);
+            Ok(())
+        },
+        other => Err(Box::new(other)),
+    }
+}
+
+#[test]
+fn test_list_values() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/list_template_file.txt", &mut interpreter)?;
+
+    // Sanitizing strips the commas, so the items are split using the commas of the transcript
+    let transcript = "Add milk, eggs and bread to the shopping list!";
+    let (input, map) = normalizer::sanitize(transcript);
+    assert_eq!("add milk eggs and bread to the shopping list", input);
+    let result = handler.find_function(&input)?.unwrap();
+    let items = vec![String::from("milk"), String::from("eggs"), String::from("bread")];
+    assert_eq!(Some(SlotValue::List(items)), result.raw_value("items", transcript, &map));
+    Ok(())
+}
+
+#[test]
+fn test_raw_values() -> Result<(), Box<dyn Error>> {
+    let mut interpreter = CortexInterpreter::new()?;
+    let mut handler = TemplateHandler::new();
+    handler.set_normalizer(Normalizer::standard())?;
+    handler.load_from_file("./tests/res/ranking_template_file.txt", &mut interpreter)?;
+
+    // Text slots keep the capitalization and punctuation of the transcript
+    let transcript = "Play AC/DC's Back In Black, please";
+    let (input, map) = normalizer::sanitize(transcript);
+    let result = handler.find_function(&input)?.unwrap();
+    assert_eq!(Some(&SlotValue::Text(String::from("acdc's back in black please"))), result.match_inst.get_value("thing"));
+    assert_eq!(Some(SlotValue::Text(String::from("AC/DC's Back In Black, please"))), result.raw_value("thing", transcript, &map));
+
+    let mut handler = TemplateHandler::new();
+    handler.load_from_file("./tests/res/list_template_file.txt", &mut interpreter)?;
+    let transcript = "Add Milk, Eggs And Peanut Butter to the shopping list";
+    let (input, map) = normalizer::sanitize(transcript);
+    let result = handler.find_function(&input)?.unwrap();
+    let items = vec![String::from("Milk"), String::from("Eggs"), String::from("Peanut Butter")];
+    assert_eq!(Some(SlotValue::List(items)), result.raw_value("items", transcript, &map));
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use std::error::Error;
 
-use homeboy::templating::{parser::TemplateParser, template::{Clause, Symbol, SymbolInternal, Template}};
+use homeboy::templating::{parser::TemplateParser, template::{Clause, Slot, SlotModifiers, SlotType, Symbol, SymbolInternal, Template}};
 
 #[test]
 fn template_parsing_tests() -> Result<(), Box<dyn Error>> {
@@ -10,11 +10,42 @@ fn template_parsing_tests() -> Result<(), Box<dyn Error>> {
     run_test("{hello}", Template::single(Clause::single(Symbol::new(SymbolInternal::SubtemplateCall(String::from("hello")), false))))?;
     run_test("{hello}?", Template::single(Clause::single(Symbol::new(SymbolInternal::SubtemplateCall(String::from("hello")), true))))?;
 
-    run_test("[hello]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(String::from("hello")), false))))?;
-    run_test("[hello]?", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(String::from("hello")), true))))?;
+    run_test("[hello]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::new("hello")), false))))?;
+    run_test("[hello]?", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::new("hello")), true))))?;
+
+    run_test("[count:number]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("count", SlotType::Number)), false))))?;
+    run_test("[when:duration]?", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("when", SlotType::Duration)), true))))?;
+    run_test("[on:bool]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("on", SlotType::Bool)), false))))?;
+    run_test("[items...]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("items", SlotType::List)), false))))?;
+    run_test("[room:{rooms}]", Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("room", SlotType::Entity(String::from("rooms")))), false))))?;
+    assert!(TemplateParser::parse_template("[count:colour]").is_err());
+    assert!(TemplateParser::parse_template("[count:number...]").is_err());
+
+    run_test("what's", Template::single(Clause::single(Symbol::new(SymbolInternal::Text(String::from("what's")), false))))?;
+    run_test("up to 11", Template::single(Clause::new(vec![
+        Symbol::new(SymbolInternal::Text(String::from("up")), false),
+        Symbol::new(SymbolInternal::Text(String::from("to")), false),
+        Symbol::new(SymbolInternal::Text(String::from("11")), false),
+    ])))?;
+    run_test("café", Template::single(Clause::single(Symbol::new(SymbolInternal::Text(String::from("café")), false))))?;
+
+    run_test("{toggle(light)}", Template::single(Clause::single(Symbol::new(SymbolInternal::ParameterizedCall(String::from("toggle"), vec![
+        Template::single(Clause::single(Symbol::new(SymbolInternal::Text(String::from("light")), false))),
+    ]), false))))?;
+    run_test("{set(kitchen light, [level:number])}?", Template::single(Clause::single(Symbol::new(SymbolInternal::ParameterizedCall(String::from("set"), vec![
+        Template::single(Clause::new(vec![
+            Symbol::new(SymbolInternal::Text(String::from("kitchen")), false),
+            Symbol::new(SymbolInternal::Text(String::from("light")), false),
+        ])),
+        Template::single(Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::typed("level", SlotType::Number)), false))),
+    ]), true))))?;
+    run_test("the $device", Template::single(Clause::new(vec![
+        Symbol::new(SymbolInternal::Text(String::from("the")), false),
+        Symbol::new(SymbolInternal::Parameter(String::from("device")), false),
+    ])))?;
 
     run_test("[hello]|hello|{hello}", Template::new(vec![
-        Clause::single(Symbol::new(SymbolInternal::VarBind(String::from("hello")), false)),
+        Clause::single(Symbol::new(SymbolInternal::VarBind(Slot::new("hello")), false)),
         Clause::single(Symbol::new(SymbolInternal::Text(String::from("hello")), false)),
         Clause::single(Symbol::new(SymbolInternal::SubtemplateCall(String::from("hello")), false)),
     ]))?;
@@ -45,6 +76,46 @@ fn template_parsing_tests() -> Result<(), Box<dyn Error>> {
         Symbol::new(SymbolInternal::Text(String::from("you")), false),
     ])))?;
 
+    run_test("turn (on|off)=state {please}=polite?", Template::single(Clause::new(vec![
+        Symbol::new(SymbolInternal::Text(String::from("turn")), false),
+        Symbol::labeled(SymbolInternal::Template(Box::new(Template::new(vec![
+            Clause::single(Symbol::new(SymbolInternal::Text(String::from("on")), false)),
+            Clause::single(Symbol::new(SymbolInternal::Text(String::from("off")), false)),
+        ]))), false, "state"),
+        Symbol::labeled(SymbolInternal::SubtemplateCall(String::from("please")), true, "polite"),
+    ])))?;
+    run_test("(on|off)=state the $device please", Template::single(Clause::new(vec![
+        Symbol::labeled(SymbolInternal::Template(Box::new(Template::new(vec![
+            Clause::single(Symbol::new(SymbolInternal::Text(String::from("on")), false)),
+            Clause::single(Symbol::new(SymbolInternal::Text(String::from("off")), false)),
+        ]))), false, "state"),
+        Symbol::new(SymbolInternal::Text(String::from("the")), false),
+        Symbol::new(SymbolInternal::Parameter(String::from("device")), false),
+        Symbol::new(SymbolInternal::Text(String::from("please")), false),
+    ])))?;
+    run_test("[task:lazy:1..8] * [items...:required:2..]", Template::single(Clause::new(vec![
+        Symbol::new(SymbolInternal::VarBind(Slot::new("task").with_modifiers(SlotModifiers {
+            lazy: true,
+            required: false,
+            words: Some((1, Some(8))),
+        })), false),
+        Symbol::new(SymbolInternal::Wildcard, false),
+        Symbol::new(SymbolInternal::VarBind(Slot::typed("items", SlotType::List).with_modifiers(SlotModifiers {
+            lazy: false,
+            required: true,
+            words: Some((2, None)),
+        })), false),
+    ])))?;
+    // Modifiers only apply to text and list slots, and bounds must leave room for at least one word
+    assert!(TemplateParser::parse_template("[count:number:lazy]").is_err());
+    assert!(TemplateParser::parse_template("[task:3..1]").is_err());
+    assert!(TemplateParser::parse_template("[task:0..0]").is_err());
+    assert!(TemplateParser::parse_template("[task:fast]").is_err());
+
+    // Only groups and subtemplate calls can be labeled
+    assert!(TemplateParser::parse_template("turn on=state").is_err());
+    assert!(TemplateParser::parse_template("[song]=title").is_err());
+
     Ok(())
 }
 
@@ -53,3 +124,45 @@ fn run_test(input: &str, expected: Template) -> Result<(), Box<dyn Error>> {
     assert_eq!(expected, template);
     Ok(())
 }
+
+#[test]
+fn round_trip_tests() -> Result<(), Box<dyn Error>> {
+    let canonical = [
+        "hello",
+        "{pre command ask}? play [song] on spotify",
+        "(can|would) you please?",
+        "set a timer for [length:duration]|(start|begin)=verb a [length:duration] timer",
+        "[items...] [room:{rooms}] [n:number] [name:required] [task:lazy:1..8] [rest:2..]",
+        "{toggle(light|lamp, [room])}=device? the $device *",
+        "(a|(b|c d?)?)|",
+        "what's up to 11 in the café",
+    ];
+    for input in canonical {
+        assert_round_trip(input, input)?;
+    }
+
+    assert_round_trip("( could | would )you   please ?", "(could|would) you please?")?;
+    assert_round_trip("{ toggle ( light ) } = device", "{toggle(light)}=device")?;
+    Ok(())
+}
+
+fn assert_round_trip(input: &str, expected: &str) -> Result<(), Box<dyn Error>> {
+    let template = TemplateParser::parse_template(input)?;
+    assert_eq!(expected, template.to_string());
+    assert_eq!(template, TemplateParser::parse_template(&template.to_string())?);
+
+    let json = serde_json::to_string(&template)?;
+    assert_eq!(template, serde_json::from_str::<Template>(&json)?);
+    Ok(())
+}
+
+#[test]
+fn all_symbols_tests() -> Result<(), Box<dyn Error>> {
+    let template = TemplateParser::parse_template("{ask}? (turn|switch)=verb {toggle(light|[thing])} [room:{rooms}]")?;
+    let symbols: Vec<String> = template.all_symbols().iter().map(|s| s.to_string()).collect();
+    // Symbols inside groups and arguments follow the symbol that contains them
+    assert_eq!(vec![
+        "{ask}?", "(turn|switch)=verb", "turn", "switch", "{toggle(light|[thing])}", "light", "[thing]", "[room:{rooms}]",
+    ], symbols);
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use homeboy::templating::textmap::{self, TextMap};
+
+#[test]
+fn text_map_tests() {
+    // "Hello, World" -> "hello world", a character at a time
+    let mut map = TextMap::new();
+    map.push(0..1, 0..1);
+    for i in 1..5 {
+        map.push_copied(i..i + 1, i);
+    }
+    map.push_copied(5..6, 6);
+    map.push(6..7, 7..8);
+    for i in 7..11 {
+        map.push_copied(i..i + 1, i + 1);
+    }
+    assert_eq!(Some(0..5), map.original_range(0..5));
+    assert_eq!(Some(7..12), map.original_range(6..11));
+    assert_eq!(Some(2..10), map.original_range(2..9));
+    assert_eq!(None, map.original_range(3..3));
+    assert_eq!(None, map.original_range(11..20));
+
+    // "hello world" -> "hello there world", where "there" was added
+    let mut next = TextMap::new();
+    next.push_copied(0..5, 0);
+    next.push_copied(12..17, 6);
+    assert_eq!(None, next.original_range(6..11));
+    assert_eq!(Some(0..11), next.original_range(0..17));
+
+    // Rewritten pieces only map as a whole, even when their length is unchanged ("straße" -> "strasse")
+    let mut changed = TextMap::new();
+    changed.push(0..2, 0..8);
+    assert_eq!(Some(0..8), changed.original_range(1..2));
+    let mut folded = TextMap::new();
+    folded.push(0..7, 0..7);
+    assert_eq!(Some(0..7), folded.original_range(4..5));
+
+    assert_eq!(Some(2..4), TextMap::identity("abcdef").original_range(2..4));
+}
+
+#[test]
+fn word_range_tests() {
+    assert_eq!(vec![0..4, 6..9, 10..14], textmap::word_ranges("play  the song\t"));
+    assert_eq!(vec![1..6], textmap::word_ranges(" café"));
+    assert!(textmap::word_ranges("   ").is_empty());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::{error::Error, fs::{self, File}, path::PathBuf, time::{Duration, SystemTime}};
+
+use common::temp_dir;
+use homeboy::runner::watch::watch::FileWatcher;
+
+fn set_modified(path: &PathBuf, time: SystemTime) -> Result<(), Box<dyn Error>> {
+    File::options().write(true).open(path)?.set_modified(time)?;
+    Ok(())
+}
+
+#[test]
+fn test_file_watcher() -> Result<(), Box<dyn Error>> {
+    let dir = temp_dir("watcher")?;
+    let kept = dir.join("kept.txt");
+    let edited = dir.join("edited.txt");
+    let deleted = dir.join("deleted.txt");
+    let created = dir.join("created.txt");
+    for path in [&kept, &edited, &deleted] {
+        fs::write(path, "")?;
+    }
+
+    assert!(!FileWatcher::new(std::slice::from_ref(&kept)).has_changed());
+
+    let watcher = FileWatcher::new(&[kept.clone(), edited.clone()]);
+    set_modified(&edited, SystemTime::now() + Duration::from_secs(60))?;
+    assert!(watcher.has_changed());
+
+    let watcher = FileWatcher::new(&[kept.clone(), deleted.clone()]);
+    fs::remove_file(&deleted)?;
+    assert!(watcher.has_changed());
+
+    let watcher = FileWatcher::new(&[kept.clone(), created.clone()]);
+    assert!(!watcher.has_changed());
+    fs::write(&created, "")?;
+    assert!(watcher.has_changed());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}